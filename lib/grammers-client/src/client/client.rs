@@ -9,9 +9,10 @@ use crate::utils::{AsyncMutex, Mutex};
 use grammers_mtproto::{mtp, transport};
 use grammers_mtsender::{Enqueuer, Sender};
 use grammers_session::{ChatHashCache, MessageBox, Session};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 use std::net::SocketAddr;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Notify;
@@ -92,8 +93,27 @@ pub struct InitParams {
     ///
     /// When the limit is `Some`, a buffer to hold that many updates will be pre-allocated.
     pub update_queue_limit: Option<usize>,
+    /// How many times a download may be redirected to a different data center.
+    ///
+    /// Files may be stored in a data center other than the one the client is connected to. In
+    /// this case, Telegram responds with a `FILE_MIGRATE` error, and the download is retried in
+    /// the data center where the file lives, connecting (and logging in) to it if needed.
+    ///
+    /// Telegram should never need more than a single redirect, which is the default. A limit of
+    /// zero (`0`) disables redirects, so that the error is returned instead.
+    pub max_file_migrations: usize,
+    /// How many parts of a big file may be uploaded at the same time.
+    ///
+    /// Telegram accepts the parts of big files (those over 10 MiB) in any order, so sending
+    /// several at once makes uploads faster, at the cost of using more memory (each part in
+    /// flight is 512 KiB). The default is 4. A value of zero (`0`) is treated as one. Small files
+    /// are always uploaded one part at a time.
+    pub upload_concurrency: usize,
 }
 
+/// A connection to a data center other than the one the client is logged in to.
+pub(crate) type ExportedSender = Arc<AsyncMutex<Sender<transport::Full, mtp::Encrypted>>>;
+
 pub(crate) struct ClientInner {
     // Used to implement `PartialEq`.
     pub(crate) id: i64,
@@ -109,6 +129,10 @@ pub(crate) struct ClientInner {
     pub(crate) updates: Mutex<VecDeque<crate::types::Update>>,
     // Used to avoid locking the entire sender when enqueueing requests.
     pub(crate) request_tx: Mutex<Enqueuer>,
+    // Connections to data centers other than `dc_id`, used to download files stored there.
+    pub(crate) exported_senders: Mutex<HashMap<i32, ExportedSender>>,
+    // Cleared once reading from the connection fails, as it can't be used after that.
+    pub(crate) connected: AtomicBool,
 }
 
 /// A client capable of connecting to Telegram and invoking requests.
@@ -121,6 +145,10 @@ pub(crate) struct ClientInner {
 /// On drop, all state is synchronized to the session. The [`Session`] must be explicitly saved
 /// to disk with [`Session::save_to_file`] for persistence
 ///
+/// Cloning the client is cheap, and all clones share the same connection and state, so they
+/// can be freely moved into other tasks and used at the same time. The media types keep a
+/// clone of the client they came from in order to download themselves.
+///
 /// [`Session`]: grammers_session::Session
 #[derive(Clone)]
 pub struct Client(pub(crate) Arc<ClientInner>);
@@ -154,6 +182,8 @@ impl Default for InitParams {
             server_addr: None,
             flood_sleep_threshold: Some(60),
             update_queue_limit: Some(100),
+            max_file_migrations: 1,
+            upload_concurrency: 4,
         }
     }
 }
@@ -179,3 +209,15 @@ impl PartialEq for Client {
         self.0.id == other.0.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_can_be_shared_between_tasks() {
+        // Media and iterators keep clones of the client, which must be usable from any task.
+        fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+        assert_shareable::<Client>();
+    }
+}
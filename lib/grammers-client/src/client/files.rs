@@ -9,24 +9,378 @@
 use crate::types::{Media, Uploaded};
 use crate::utils::{generate_random_id, AsyncMutex};
 use crate::Client;
+use bytes::Bytes;
 use futures_util::future::try_join_all;
+use futures_util::ready;
+use futures_util::stream::{self, Stream, StreamExt as _};
 use grammers_mtsender::InvocationError;
 use grammers_tl_types as tl;
-use std::{io::SeekFrom, path::Path, sync::Arc};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{fmt, io::SeekFrom, path::Path, sync::Arc};
 use tokio::{
     fs,
-    io::{self, AsyncRead, AsyncReadExt as _, AsyncSeekExt as _, AsyncWriteExt as _},
+    io::{
+        self, AsyncRead, AsyncReadExt as _, AsyncSeekExt as _, AsyncWrite, AsyncWriteExt as _,
+        ReadBuf,
+    },
+    sync::mpsc,
+    time::{sleep, Instant},
 };
 
 pub const MIN_CHUNK_SIZE: i32 = 4 * 1024;
 pub const MAX_CHUNK_SIZE: i32 = 512 * 1024;
 const BIG_FILE_SIZE: usize = 10 * 1024 * 1024;
-const WORKER_COUNT: usize = 4;
+/// The largest chunk Telegram serves in a single download request.
+const MAX_DOWNLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+/// The maximum amount of ranges a single parallel download will fetch at the same time.
+///
+/// All the ranges share the client's connection to the data center where the file is, so this
+/// limits how many requests are in flight rather than how many connections are opened.
+pub const MAX_DOWNLOAD_CONNECTIONS: usize = 8;
+
+/// The error type which is returned when downloading a file fails.
+///
+/// This is the error of every download method, whether the media is written to a file, a
+/// writer or memory. More variants may be added as downloads gain features.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DownloadError {
+    /// Creating or writing to the destination failed.
+    Io(io::Error),
+    /// The request to fetch the file's contents failed.
+    Rpc(InvocationError),
+    /// The media has nothing that can be downloaded, such as an empty document.
+    NoLocation,
+    /// The photo has no sizes that can be downloaded.
+    NoSize,
+    /// The file reference of the media has expired, so it can't be downloaded anymore.
+    ///
+    /// Fetching the message containing the media again gives it a fresh file reference.
+    /// [`Message::download_media`](crate::types::Message::download_media) does this
+    /// automatically, once.
+    FileReferenceExpired,
+    /// The media is not attached to any client, so it can't be downloaded.
+    ///
+    /// This happens with media that was deserialized, until a client is attached to it with
+    /// [`Media::with_client`](crate::types::Media::with_client) (or the `set_client` method of
+    /// [`Photo`](crate::types::Photo), [`Document`](crate::types::Document) and
+    /// [`Size`](crate::types::photo_sizes::Size)).
+    Detached,
+    /// The client lost its connection to Telegram, so nothing can be downloaded with it.
+    ///
+    /// This is checked before the download starts (and before any file is created), as
+    /// described in [`Client::is_connected`].
+    NotConnected,
+    /// The download finished, but fewer or more bytes than the file should have were received,
+    /// for example because the connection was dropped.
+    ///
+    /// This check can be turned off with [`DownloadOptions::verify_size`].
+    SizeMismatch {
+        /// The size of the file reported by Telegram.
+        expected: u64,
+        /// How many bytes were actually downloaded.
+        got: u64,
+    },
+    /// The photo or document has no thumbnail of the requested type.
+    ///
+    /// Thumbnail types are single letters like `"m"` or `"x"`, which are only valid for the
+    /// media they were taken from.
+    UnknownThumbType(String),
+    /// Telegram asked to wait this many seconds before downloading more, and the wait was
+    /// longer than [`DownloadOptions::max_flood_wait`] (or happened too many times).
+    FloodWait(u32),
+    /// The download was stopped through its [`CancellationToken`].
+    ///
+    /// Whatever was downloaded until then is kept, and ends on a chunk boundary, so the
+    /// download can be continued later (for example, with
+    /// [`Document::download_resume`](crate::types::media::Document::download_resume)).
+    Cancelled,
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "download error: {}", e),
+            Self::Rpc(e) => write!(f, "download error: {}", e),
+            Self::NoLocation => write!(f, "download error: media has no downloadable location"),
+            Self::NoSize => write!(f, "download error: photo has no downloadable size"),
+            Self::FileReferenceExpired => write!(f, "download error: file reference expired"),
+            Self::Detached => write!(f, "download error: media has no client attached"),
+            Self::NotConnected => write!(f, "download error: client is not connected"),
+            Self::Cancelled => write!(f, "download error: cancelled"),
+            Self::FloodWait(secs) => {
+                write!(f, "download error: flood wait of {} seconds", secs)
+            }
+            Self::UnknownThumbType(ty) => {
+                write!(f, "download error: no thumbnail of type {:?}", ty)
+            }
+            Self::SizeMismatch { expected, got } => write!(
+                f,
+                "download error: expected {} bytes but got {}",
+                expected, got
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Rpc(e) => Some(e),
+            Self::NoLocation
+            | Self::NoSize
+            | Self::FileReferenceExpired
+            | Self::Detached
+            | Self::NotConnected
+            | Self::SizeMismatch { .. }
+            | Self::UnknownThumbType(_)
+            | Self::FloodWait(_)
+            | Self::Cancelled => None,
+        }
+    }
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<InvocationError> for DownloadError {
+    fn from(error: InvocationError) -> Self {
+        if error.is("FILE_REFERENCE_EXPIRED") {
+            Self::FileReferenceExpired
+        } else if let Some(secs) = flood_wait(&error) {
+            Self::FloodWait(secs)
+        } else {
+            Self::Rpc(error)
+        }
+    }
+}
+
+impl From<DownloadError> for io::Error {
+    fn from(error: DownloadError) -> Self {
+        match error {
+            DownloadError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::Other, e),
+        }
+    }
+}
+
+/// The error type which is returned when re-uploading media with [`Client::transfer`] fails.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TransferError {
+    /// Fetching the contents of the media failed, including when it has a different size than
+    /// it said it would ([`DownloadError::SizeMismatch`]).
+    Download(DownloadError),
+    /// Storing the new file failed, as it would with [`Client::upload_stream`].
+    Upload(io::Error),
+    /// The media doesn't say how large it is, which is needed to upload it.
+    UnknownSize,
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Download(e) => write!(f, "transfer error: {}", e),
+            Self::Upload(e) => write!(f, "transfer error: upload failed: {}", e),
+            Self::UnknownSize => write!(f, "transfer error: media has no known size"),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Download(e) => Some(e),
+            Self::Upload(e) => Some(e),
+            Self::UnknownSize => None,
+        }
+    }
+}
+
+impl From<DownloadError> for TransferError {
+    fn from(error: DownloadError) -> Self {
+        Self::Download(error)
+    }
+}
+
+/// Options to tweak how files are downloaded.
+///
+/// # Examples
+///
+/// ```
+/// use grammers_client::DownloadOptions;
+///
+/// let options = DownloadOptions {
+///     chunk_size: 128 * 1024,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct DownloadOptions {
+    /// How many bytes to request at once.
+    ///
+    /// Telegram requires this to be a multiple of [`MIN_CHUNK_SIZE`] that also evenly divides
+    /// 1 MiB. In other words, it must be a power of two between 4 KiB and 1 MiB. Larger chunks
+    /// need fewer requests, but each takes longer. The default is [`MAX_CHUNK_SIZE`], 512 KiB.
+    pub chunk_size: usize,
+
+    /// Whether to fail with [`DownloadError::SizeMismatch`] when the amount of bytes downloaded
+    /// differs from the size Telegram reported for the file. Files without a known size are
+    /// never checked. Enabled by default.
+    pub verify_size: bool,
+
+    /// A token which can be used to stop the download before it completes, with
+    /// [`DownloadError::Cancelled`]. By default, downloads can't be cancelled.
+    pub cancellation: Option<CancellationToken>,
+
+    /// The maximum average amount of bytes per second to download, paced with a token bucket
+    /// that allows bursts of up to one second's worth of data. By default, there's no limit.
+    ///
+    /// The limit applies to each download on its own, so several downloads using the same
+    /// options can together exceed it.
+    pub max_bytes_per_sec: Option<u64>,
+
+    /// The longest flood wait to sleep through before retrying a chunk. Telegram asks clients
+    /// that make too many requests to wait before the next one, which happens easily when
+    /// downloading many files at once. Longer waits fail with [`DownloadError::FloodWait`], so
+    /// the caller can decide what to do. The default is one minute.
+    pub max_flood_wait: Duration,
+
+    /// How many times to sleep through a flood wait and retry during a single download, after
+    /// which flood waits fail with [`DownloadError::FloodWait`]. The default is 3.
+    ///
+    /// Downloads which don't take options, such as [`Client::iter_download`], use the defaults.
+    /// Each range of a parallel download is retried on its own.
+    pub flood_wait_retries: usize,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: MAX_CHUNK_SIZE as usize,
+            verify_size: true,
+            cancellation: None,
+            max_bytes_per_sec: None,
+            max_flood_wait: Duration::from_secs(60),
+            flood_wait_retries: 3,
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// Fail with an `InvalidInput` error if Telegram would reject the options.
+    pub(crate) fn check(&self) -> Result<(), DownloadError> {
+        if !(MIN_CHUNK_SIZE as usize..=MAX_DOWNLOAD_CHUNK_SIZE).contains(&self.chunk_size)
+            || self.chunk_size % MIN_CHUNK_SIZE as usize != 0
+            || MAX_DOWNLOAD_CHUNK_SIZE % self.chunk_size != 0
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "chunk size must be a multiple of {} that divides {}, got {}",
+                    MIN_CHUNK_SIZE, MAX_DOWNLOAD_CHUNK_SIZE, self.chunk_size
+                ),
+            )
+            .into());
+        }
+        if self.max_bytes_per_sec == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the download rate limit must be greater than zero",
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Token bucket used to limit the download rate. Chunks are paid for once they arrive, which
+/// delays requesting the next one until the bucket is no longer in debt.
+struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            rate: bytes_per_sec as f64,
+            tokens: bytes_per_sec as f64,
+            last: Instant::now(),
+        }
+    }
+
+    async fn consume(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate) - bytes as f64;
+        self.last = now;
+        if self.tokens < 0.0 {
+            sleep(Duration::from_secs_f64(-self.tokens / self.rate)).await;
+        }
+    }
+}
+
+/// A handle used to cancel downloads, which can be cloned and shared with other tasks.
+///
+/// Cancelling is checked before requesting every chunk, so the download stops after the chunk
+/// in flight (if any) has been written.
+///
+/// # Examples
+///
+/// ```
+/// use grammers_client::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let handle = token.clone();
+/// // ...start a download with `token`, and later, from somewhere else:
+/// handle.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a new token, which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel all downloads using this token (or any of its clones).
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// Tokens are equal only if they're clones of each other, as they would cancel together.
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
 
 pub struct DownloadIter {
     client: Client,
     done: bool,
     request: tl::functions::upload::GetFile,
+    // The data center where the file lives, once Telegram told us it's not the client's.
+    dc_id: Option<i32>,
+    migrations_left: usize,
+    max_flood_wait: Duration,
+    flood_wait_retries: usize,
 }
 
 impl DownloadIter {
@@ -41,9 +395,14 @@ impl DownloadIter {
     fn new_from_file_location(client: &Client, location: tl::enums::InputFileLocation) -> Self {
         // TODO let users tweak all the options from the request
         // TODO cdn support
+        let options = DownloadOptions::default();
         Self {
             client: client.clone(),
             done: false,
+            dc_id: None,
+            migrations_left: client.0.config.params.max_file_migrations,
+            max_flood_wait: options.max_flood_wait,
+            flood_wait_retries: options.flood_wait_retries,
             request: tl::functions::upload::GetFile {
                 precise: false,
                 cdn_supported: false,
@@ -67,6 +426,16 @@ impl DownloadIter {
         self
     }
 
+    /// Use the chunk size and flood wait handling of the options, which must have been checked.
+    ///
+    /// Unlike [`DownloadIter::chunk_size`], this allows chunks of up to 1 MiB.
+    pub(crate) fn options(mut self, options: &DownloadOptions) -> Self {
+        self.request.limit = options.chunk_size as i32;
+        self.max_flood_wait = options.max_flood_wait;
+        self.flood_wait_retries = options.flood_wait_retries;
+        self
+    }
+
     /// Skips `n` chunks to start downloading a different offset from the file. If you want to
     /// skip less data, modify the `chunk_size` before calling this method, and then reset it to
     /// any value you want.
@@ -76,6 +445,9 @@ impl DownloadIter {
     }
 
     /// Fetch and return the next chunk.
+    ///
+    /// Flood waits of up to a minute are slept through, and the chunk is requested again, at
+    /// most 3 times during the download (as with the default [`DownloadOptions`]).
     pub async fn next(&mut self) -> Result<Option<Vec<u8>>, InvocationError> {
         if self.done {
             return Ok(None);
@@ -83,8 +455,34 @@ impl DownloadIter {
 
         use tl::enums::upload::File;
 
-        // TODO handle FILE_MIGRATE and maybe FILEREF_UPGRADE_NEEDED
-        match self.client.invoke(&self.request).await? {
+        // TODO maybe handle FILEREF_UPGRADE_NEEDED
+        let file = loop {
+            let result = match self.dc_id {
+                Some(dc_id) => self.client.invoke_in_dc(&self.request, dc_id).await,
+                None => self.client.invoke(&self.request).await,
+            };
+            match result {
+                // Without a data center to migrate to, this is like any other error.
+                Err(InvocationError::Rpc(err))
+                    if err.is("FILE_MIGRATE")
+                        && err.value.is_some()
+                        && self.migrations_left > 0 =>
+                {
+                    self.migrations_left -= 1;
+                    self.dc_id = err.value.map(|dc_id| dc_id as i32);
+                }
+                Err(e) => match flood_wait_delay(&e, self.max_flood_wait) {
+                    Some(delay) if self.flood_wait_retries > 0 => {
+                        self.flood_wait_retries -= 1;
+                        sleep(delay).await;
+                    }
+                    _ => return Err(e),
+                },
+                Ok(file) => break file,
+            }
+        };
+
+        match file {
             File::File(f) => {
                 if f.bytes.len() < self.request.limit as usize {
                     self.done = true;
@@ -131,7 +529,8 @@ impl Client {
     /// If the file already exists, it will be overwritten.
     ///
     /// This is a small wrapper around [`Client::iter_download`] for the common case of
-    /// wanting to save the file locally.
+    /// wanting to save the file locally. Returns [`DownloadError::NoLocation`] if the media
+    /// has nothing to download, such as a geographical point or a dice.
     ///
     /// # Examples
     ///
@@ -145,35 +544,268 @@ impl Client {
         &self,
         media: &Media,
         path: P,
-    ) -> Result<(), io::Error> {
-        let mut download = self.iter_download(media);
+    ) -> Result<(), DownloadError> {
+        let location = media.to_input_location().ok_or(DownloadError::NoLocation)?;
+        self.download_media_at_location(location, path).await
+    }
 
-        Client::load(path, &mut download).await
+    /// Downloads the file at the given location fully into memory.
+    ///
+    /// This is meant for advanced uses where the raw location of the file is already known.
+    /// Chunks are appended to the buffer as they arrive, so this needs as much memory as the
+    /// file is large. Prefer [`Client::download_media`] for large files.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(location: grammers_tl_types::enums::InputFileLocation, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let bytes = client.download_media_to_vec(location).await?;
+    /// println!("Downloaded {} bytes", bytes.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_media_to_vec(
+        &self,
+        location: tl::enums::InputFileLocation,
+    ) -> Result<Vec<u8>, DownloadError> {
+        connected(self)?;
+        let mut buffer = Vec::new();
+        let mut download = DownloadIter::new_from_location(self, location);
+        let options = DownloadOptions::default();
+        Client::load_into(&mut buffer, &mut download, None, &options, |_, _| {}).await?;
+        Ok(buffer)
     }
 
     pub(crate) async fn download_media_at_location<P: AsRef<Path>>(
         &self,
         location: tl::enums::InputFileLocation,
         path: P,
-    ) -> Result<(), io::Error> {
+    ) -> Result<(), DownloadError> {
+        connected(self)?;
         let mut download = DownloadIter::new_from_location(self, location);
 
         Client::load(path, &mut download).await
     }
 
-    async fn load<P: AsRef<Path>>(path: P, download: &mut DownloadIter) -> Result<(), io::Error> {
+    /// Stream the file at the location chunk by chunk, or fail with
+    /// [`DownloadError::NoLocation`] if there is none.
+    pub(crate) fn download_location_stream(
+        &self,
+        location: Option<tl::enums::InputFileLocation>,
+    ) -> impl Stream<Item = Result<Bytes, DownloadError>> {
+        let download = location.map(|location| DownloadIter::new_from_location(self, location));
+
+        stream::try_unfold(download, |download| async move {
+            let mut download = download.ok_or(DownloadError::NoLocation)?;
+            let chunk = download.next().await?;
+            Ok(chunk.map(|chunk| (Bytes::from(chunk), Some(download))))
+        })
+    }
+
+    /// Download the file at the location into the given path, fetching `connections` contiguous
+    /// ranges of it at the same time, each written at its own offset in the file.
+    ///
+    /// Fails with [`DownloadError::SizeMismatch`] if any range comes back short, as that would
+    /// leave a hole in the pre-allocated file.
+    pub(crate) async fn download_location_parallel(
+        &self,
+        location: tl::enums::InputFileLocation,
+        path: &Path,
+        total: u64,
+        connections: usize,
+    ) -> Result<(), DownloadError> {
+        let chunk_size = MAX_CHUNK_SIZE as u64;
+        let file = fs::File::create(path).await?;
+        file.set_len(total).await?;
+        drop(file);
+
+        let tasks = chunk_ranges(total, chunk_size, connections)
+            .into_iter()
+            .map(|(first, last)| {
+                let location = location.clone();
+                async move {
+                    let mut file = fs::OpenOptions::new().write(true).open(path).await?;
+                    file.seek(SeekFrom::Start(first * chunk_size)).await?;
+
+                    let mut download =
+                        DownloadIter::new_from_location(self, location).skip_chunks(first as i32);
+                    let mut written = 0;
+                    for _ in first..last {
+                        match download.next().await? {
+                            Some(chunk) => {
+                                file.write_all(&chunk).await?;
+                                written += chunk.len() as u64;
+                            }
+                            None => break,
+                        }
+                    }
+                    file.flush().await?;
+                    let expected = (last * chunk_size).min(total) - first * chunk_size;
+                    Ok::<_, DownloadError>((written, expected))
+                }
+            });
+
+        let ranges = try_join_all(tasks).await?;
+        let got = ranges.iter().map(|(written, _)| written).sum();
+        if ranges.iter().any(|(written, expected)| written != expected) {
+            return Err(DownloadError::SizeMismatch {
+                expected: total,
+                got,
+            });
+        }
+        Ok(())
+    }
+
+    /// Download `limit` bytes of the file at the location, starting at `offset`.
+    ///
+    /// Telegram only serves aligned chunks, so whole chunks covering the range are fetched and
+    /// then trimmed. Fewer bytes are returned if the file ends before the range does.
+    pub(crate) async fn download_location_range(
+        &self,
+        location: tl::enums::InputFileLocation,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<u8>, DownloadError> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let (chunk_size, first, count) = range_chunks(offset, limit);
+        let mut download = DownloadIter::new_from_location(self, location)
+            .chunk_size(chunk_size as i32)
+            .skip_chunks(first as i32);
+
+        // The limit may go far past the end of the file, so the buffer only grows as needed.
+        let mut buffer = Vec::new();
+        for _ in 0..count {
+            match download.next().await? {
+                Some(chunk) => buffer.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+
+        Ok(trim_range(buffer, offset - first * chunk_size, limit))
+    }
+
+    /// Download the file at the location into the writer with the given options, calling
+    /// `progress` after every chunk with the bytes written so far and the expected `total`.
+    pub(crate) async fn download_location_into<W, F>(
+        &self,
+        location: tl::enums::InputFileLocation,
+        writer: &mut W,
+        options: &DownloadOptions,
+        total: Option<u64>,
+        progress: F,
+    ) -> Result<u64, DownloadError>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(u64, Option<u64>),
+    {
+        options.check()?;
+        let mut download = DownloadIter::new_from_location(self, location).options(options);
+
+        let written = Client::load_into(writer, &mut download, total, options, progress).await?;
+        if options.verify_size {
+            check_size(total, written)?;
+        }
+        Ok(written)
+    }
+
+    pub(crate) async fn download_media_prefix_at_location<P: AsRef<Path>>(
+        &self,
+        location: tl::enums::InputFileLocation,
+        path: P,
+        len: usize,
+    ) -> Result<(), DownloadError> {
+        // Prefixes are usually small, so avoid fetching far more than needed. Telegram requires
+        // the chunk size to evenly divide 1MB, so it must be a power of two.
+        let chunk_size = (len.next_power_of_two() as i32).clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE);
+        let mut download = DownloadIter::new_from_location(self, location).chunk_size(chunk_size);
+
         let mut file = fs::File::create(path).await?;
-        while let Some(chunk) = download
-            .next()
-            .await
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
-        {
-            file.write_all(&chunk).await?;
+        let mut left = len;
+        while left != 0 {
+            let chunk = match download.next().await? {
+                Some(chunk) => chunk,
+                None => break,
+            };
+            let n = chunk.len().min(left);
+            file.write_all(&chunk[..n]).await?;
+            left -= n;
         }
 
+        file.flush().await?;
         Ok(())
     }
 
+    /// Continue downloading the file at the location into the file at `path`, keeping the data
+    /// that was already downloaded.
+    pub(crate) async fn download_location_resume(
+        &self,
+        location: tl::enums::InputFileLocation,
+        path: &Path,
+        total: Option<u64>,
+    ) -> Result<(), DownloadError> {
+        let download = DownloadIter::new_from_location(self, location);
+        let chunk_size = download.request.limit as u64;
+        let (mut file, offset) = open_for_resume(path, chunk_size, total).await?;
+        if Some(offset) == total {
+            return Ok(());
+        }
+
+        let mut download = download.skip_chunks((offset / chunk_size) as i32);
+        let options = DownloadOptions::default();
+        let written =
+            Client::load_into(&mut file, &mut download, total, &options, |_, _| {}).await?;
+        check_size(total, offset + written)
+    }
+
+    async fn load<P: AsRef<Path>>(
+        path: P,
+        download: &mut DownloadIter,
+    ) -> Result<(), DownloadError> {
+        let mut file = fs::File::create(path).await?;
+        let options = DownloadOptions::default();
+        Client::load_into(&mut file, download, None, &options, |_, _| {}).await?;
+        Ok(())
+    }
+
+    async fn load_into<W, F>(
+        writer: &mut W,
+        download: &mut DownloadIter,
+        total: Option<u64>,
+        options: &DownloadOptions,
+        mut progress: F,
+    ) -> Result<u64, DownloadError>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(u64, Option<u64>),
+    {
+        let mut limiter = options.max_bytes_per_sec.map(RateLimiter::new);
+        let mut written = 0;
+        loop {
+            let cancellation = options.cancellation.as_ref();
+            if cancellation.is_some_and(CancellationToken::is_cancelled) {
+                writer.flush().await?;
+                return Err(DownloadError::Cancelled);
+            }
+            let chunk = match download.next().await? {
+                Some(chunk) => chunk,
+                None => break,
+            };
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+            // No lock is held and nothing is in flight while the callback runs.
+            progress(written, total);
+            if let Some(limiter) = limiter.as_mut() {
+                limiter.consume(chunk.len()).await;
+            }
+        }
+
+        writer.flush().await?;
+        Ok(written)
+    }
+
     /// Uploads an async stream to Telegram servers.
     ///
     /// The file is not sent to any chat, but can be used as media when sending messages for a
@@ -186,8 +818,9 @@ impl Client {
     /// downloaded buffer.
     ///
     /// The stream size may be less or equal to the actual length of the stream, but not more.
-    /// If it's less, you may continue to read from the stream after the method returns.
-    /// If it's more, the method will fail because it does not have enough data to read.
+    /// Exactly `size` bytes are read, so if it's less, you may continue to read from the stream
+    /// after the method returns. If it's more, the method will fail with an error of kind
+    /// `UnexpectedEof` because it does not have enough data to read.
     ///
     /// Note that Telegram uses the file name in certain methods, for example, to make sure the
     /// file is an image when trying to use send the file as photo media, so it is important that
@@ -218,6 +851,27 @@ impl Client {
         size: usize,
         name: String,
     ) -> Result<Uploaded, io::Error> {
+        self.upload_stream_with_progress(stream, size, name, |_, _| {})
+            .await
+    }
+
+    async fn upload_stream_with_progress<S, F>(
+        &self,
+        stream: &mut S,
+        size: usize,
+        name: String,
+        mut progress: F,
+    ) -> Result<Uploaded, io::Error>
+    where
+        S: AsyncRead + Unpin,
+        F: FnMut(u64, u64),
+    {
+        let mut sent = 0;
+        let mut report = |len: usize| {
+            sent += len as u64;
+            progress(sent, size as u64);
+        };
+
         let file_id = generate_random_id();
         let name = if name.is_empty() {
             "a".to_string()
@@ -231,12 +885,18 @@ impl Client {
 
         if big_file {
             let parts = Arc::new(parts);
-            let mut tasks = Vec::with_capacity(WORKER_COUNT);
-            for _ in 0..WORKER_COUNT {
+            let workers = self.0.config.params.upload_concurrency.max(1);
+            // Big files are uploaded by several workers at once. They send how much they
+            // uploaded here, so that no lock is held while calling back.
+            let (sent_tx, mut sent_rx) = mpsc::unbounded_channel();
+            let mut tasks = Vec::with_capacity(workers);
+            for _ in 0..workers {
                 let handle = self.clone();
                 let parts = Arc::clone(&parts);
+                let sent_tx = sent_tx.clone();
                 let task = async move {
                     while let Some((part, bytes)) = parts.next_part().await? {
+                        let len = bytes.len();
                         let ok = handle
                             .invoke(&tl::functions::upload::SaveBigFilePart {
                                 file_id,
@@ -253,13 +913,27 @@ impl Client {
                                 "server failed to store uploaded data",
                             ));
                         }
+                        let _ = sent_tx.send(len);
                     }
                     Ok(())
                 };
                 tasks.push(task);
             }
+            drop(sent_tx);
 
-            try_join_all(tasks).await?;
+            let mut upload = try_join_all(tasks);
+            loop {
+                tokio::select! {
+                    result = &mut upload => {
+                        result?;
+                        break;
+                    }
+                    Some(len) = sent_rx.recv() => report(len),
+                }
+            }
+            while let Ok(len) = sent_rx.try_recv() {
+                report(len);
+            }
 
             Ok(Uploaded::from_raw(
                 tl::types::InputFileBig {
@@ -268,11 +942,13 @@ impl Client {
                     name,
                 }
                 .into(),
-            ))
+            )
+            .with_size(size))
         } else {
             let mut md5 = md5::Context::new();
             while let Some((part, bytes)) = parts.next_part().await? {
                 md5.consume(&bytes);
+                let len = bytes.len();
                 let ok = self
                     .invoke(&tl::functions::upload::SaveFilePart {
                         file_id,
@@ -288,6 +964,7 @@ impl Client {
                         "server failed to store uploaded data",
                     ));
                 }
+                report(len);
             }
             Ok(Uploaded::from_raw(
                 tl::types::InputFile {
@@ -297,7 +974,56 @@ impl Client {
                     md5_checksum: format!("{:x}", md5.compute()),
                 }
                 .into(),
-            ))
+            )
+            .with_size(size))
+        }
+    }
+
+    /// Re-uploads the file in the media to Telegram servers, without saving it anywhere.
+    ///
+    /// The media is downloaded with the client it is attached to, and uploaded with this one,
+    /// so it can come from a different account. Chunks are uploaded as soon as they arrive,
+    /// so only a few parts are kept in memory at a time. This is useful to send media with an
+    /// account that can't use it directly. The result can be used like the one from
+    /// [`Client::upload_stream`], and the same rules about the `name` apply.
+    ///
+    /// Fails with [`TransferError::UnknownSize`] if the media has no [`Media::size`], and with
+    /// a [`DownloadError::SizeMismatch`] if it turns out to be of a different size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(media: grammers_client::types::Media, chat: grammers_client::types::Chat, other: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::InputMessage;
+    ///
+    /// // `media` came from a message received by another client.
+    /// let uploaded = other.transfer(&media, "video.mp4").await?;
+    /// other.send_message(&chat, InputMessage::text("Mirrored").file(uploaded)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn transfer(&self, media: &Media, name: &str) -> Result<Uploaded, TransferError> {
+        let location = media.to_input_location().ok_or(DownloadError::NoLocation)?;
+        let source = media.client().ok_or(DownloadError::Detached)?;
+        connected(source)?;
+        connected(self)?;
+        let size = media.size().ok_or(TransferError::UnknownSize)? as u64;
+
+        let chunks = Box::pin(source.download_location_stream(Some(location)));
+        let mut reader = ChunkReader::new(chunks, size);
+        match self
+            .upload_stream(&mut reader, size as usize, name.to_string())
+            .await
+        {
+            Ok(uploaded) => {
+                reader.finish().await?;
+                Ok(uploaded)
+            }
+            // The uploader only sees an I/O error when the download fails, so use the original.
+            Err(e) => Err(match reader.error.take() {
+                Some(error) => TransferError::Download(error),
+                None => TransferError::Upload(e),
+            }),
         }
     }
 
@@ -326,6 +1052,39 @@ impl Client {
     ///
     /// [`InputMessage`]: crate::InputMessage
     pub async fn upload_file<P: AsRef<Path>>(&self, path: P) -> Result<Uploaded, io::Error> {
+        self.upload_with_progress(path, |_, _| {}).await
+    }
+
+    /// Uploads a local file to Telegram servers, reporting the progress.
+    ///
+    /// After every part is stored by Telegram, `progress` is called with the number of bytes
+    /// sent so far and the size of the file. Big files upload several parts at once, so the
+    /// amount of bytes sent grows in steps of one part, but not necessarily in order. The upload
+    /// waits for the callback to return, so it should not block for long.
+    ///
+    /// Other than that, this works like [`Client::upload_file`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let uploaded_file = client
+    ///     .upload_with_progress("/home/username/videos/holidays.mp4", |sent, total| {
+    ///         println!("Uploaded {} out of {} bytes", sent, total);
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn upload_with_progress<P, F>(
+        &self,
+        path: P,
+        progress: F,
+    ) -> Result<Uploaded, io::Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(u64, u64),
+    {
         let path = path.as_ref();
 
         let mut file = fs::File::open(path).await?;
@@ -336,7 +1095,8 @@ impl Client {
         // files, so it's fine to unwrap.
         let name = path.file_name().unwrap().to_string_lossy().to_string();
 
-        self.upload_stream(&mut file, size, name).await
+        self.upload_stream_with_progress(&mut file, size, name, progress)
+            .await
     }
 }
 
@@ -347,6 +1107,7 @@ struct PartStreamInner<'a, S: AsyncRead + Unpin> {
 
 struct PartStream<'a, S: AsyncRead + Unpin> {
     inner: AsyncMutex<PartStreamInner<'a, S>>,
+    size: usize,
     total_parts: i32,
 }
 
@@ -361,6 +1122,7 @@ impl<'a, S: AsyncRead + Unpin> PartStream<'a, S> {
                     current_part: 0,
                 },
             ),
+            size,
             total_parts,
         }
     }
@@ -374,32 +1136,539 @@ impl<'a, S: AsyncRead + Unpin> PartStream<'a, S> {
         if lock.current_part >= self.total_parts {
             return Ok(None);
         }
+        // Only the last part may be smaller, and no more than `size` bytes are ever read, so
+        // the rest of the stream is left untouched.
+        let offset = lock.current_part as usize * MAX_CHUNK_SIZE as usize;
         let mut read = 0;
-        let mut buffer = vec![0; MAX_CHUNK_SIZE as usize];
+        let mut buffer = vec![0; (self.size - offset).min(MAX_CHUNK_SIZE as usize)];
 
         while read != buffer.len() {
             let n = lock.stream.read(&mut buffer[read..]).await?;
             if n == 0 {
-                if lock.current_part == self.total_parts - 1 {
-                    break;
-                } else {
-                    return Err(io::Error::new(
-                        io::ErrorKind::UnexpectedEof,
-                        "reached EOF before reaching the last file part",
-                    ));
-                }
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "reached EOF after {} bytes, but the stream size was {}",
+                        offset + read,
+                        self.size
+                    ),
+                ));
             }
             read += n;
         }
 
-        let bytes = if read == buffer.len() {
-            buffer
+        let res = Ok(Some((lock.current_part, buffer)));
+        lock.current_part += 1;
+        res
+    }
+}
+
+/// Reads the chunks of a download as they arrive, so that they can be uploaded again.
+///
+/// Downloaded chunks don't line up with uploaded parts, so only the rest of the current chunk
+/// is kept. Failed downloads are kept in `error`, as readers can only fail with I/O errors.
+struct ChunkReader<S> {
+    chunks: S,
+    chunk: Bytes,
+    received: u64,
+    expected: u64,
+    error: Option<DownloadError>,
+}
+
+impl<S: Stream<Item = Result<Bytes, DownloadError>> + Unpin> ChunkReader<S> {
+    fn new(chunks: S, expected: u64) -> Self {
+        Self {
+            chunks,
+            chunk: Bytes::new(),
+            received: 0,
+            expected,
+            error: None,
+        }
+    }
+
+    fn fail(&mut self, error: DownloadError) -> io::Error {
+        let e = io::Error::new(io::ErrorKind::Other, error.to_string());
+        self.error = Some(error);
+        e
+    }
+
+    fn size_mismatch(&self) -> DownloadError {
+        DownloadError::SizeMismatch {
+            expected: self.expected,
+            got: self.received,
+        }
+    }
+
+    /// Read whatever is left, failing if there was more than `expected`.
+    async fn finish(&mut self) -> Result<(), DownloadError> {
+        while let Some(chunk) = self.chunks.next().await {
+            self.received += chunk?.len() as u64;
+        }
+        if self.received == self.expected {
+            Ok(())
         } else {
-            buffer[..read].to_vec()
+            Err(self.size_mismatch())
+        }
+    }
+}
+
+impl<S: Stream<Item = Result<Bytes, DownloadError>> + Unpin> AsyncRead for ChunkReader<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        while this.chunk.is_empty() {
+            match ready!(Pin::new(&mut this.chunks).poll_next(cx)) {
+                Some(Ok(chunk)) => {
+                    this.received += chunk.len() as u64;
+                    this.chunk = chunk;
+                }
+                Some(Err(e)) => return Poll::Ready(Err(this.fail(e))),
+                None if this.received < this.expected => {
+                    let e = this.size_mismatch();
+                    return Poll::Ready(Err(this.fail(e)));
+                }
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let n = this.chunk.len().min(buf.remaining());
+        buf.put_slice(&this.chunk.split_to(n));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Open the file at `path` to continue downloading into it, truncated to where the download
+/// resumes from (as decided by [`resume_offset`]), which is also returned.
+async fn open_for_resume(
+    path: &Path,
+    chunk_size: u64,
+    total: Option<u64>,
+) -> io::Result<(fs::File, u64)> {
+    let existing = match fs::metadata(path).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e),
+    };
+    let offset = resume_offset(existing, chunk_size, total);
+
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.set_len(offset).await?;
+    Ok((file, offset))
+}
+
+/// Where to resume a download given how many bytes of the file already exist locally.
+///
+/// Requests must start on a chunk boundary, so any trailing partial chunk is downloaded again.
+/// A local file larger than the remote one can't be a prefix of it, so it starts over.
+fn resume_offset(existing: u64, chunk_size: u64, total: Option<u64>) -> u64 {
+    match total {
+        Some(total) if existing > total => 0,
+        Some(total) if existing == total => total,
+        _ => existing - existing % chunk_size,
+    }
+}
+
+/// The client media should be downloaded with, or [`DownloadError::Detached`] if it has none.
+///
+/// Fails with [`DownloadError::NotConnected`] if the client can't be used to download anymore.
+pub(crate) fn attached(client: &Option<Client>) -> Result<&Client, DownloadError> {
+    let client = client.as_ref().ok_or(DownloadError::Detached)?;
+    connected(client)?;
+    Ok(client)
+}
+
+/// Fail with [`DownloadError::NotConnected`] if the client lost its connection.
+fn connected(client: &Client) -> Result<(), DownloadError> {
+    if client.is_connected() {
+        Ok(())
+    } else {
+        Err(DownloadError::NotConnected)
+    }
+}
+
+/// Fail with [`DownloadError::SizeMismatch`] if `got` bytes are not the `expected` amount.
+fn check_size(expected: Option<u64>, got: u64) -> Result<(), DownloadError> {
+    match expected {
+        Some(expected) if expected != got => Err(DownloadError::SizeMismatch { expected, got }),
+        _ => Ok(()),
+    }
+}
+
+/// Split a file into at most `connections` contiguous ranges of whole chunks, as
+/// `(first, last)` pairs of chunk indices with `last` being exclusive.
+fn chunk_ranges(total: u64, chunk_size: u64, connections: usize) -> Vec<(u64, u64)> {
+    let chunks = total.div_ceil(chunk_size);
+    let connections = connections.clamp(1, MAX_DOWNLOAD_CONNECTIONS) as u64;
+    let per_connection = chunks.div_ceil(connections).max(1);
+    (0..chunks)
+        .step_by(per_connection as usize)
+        .map(|first| (first, (first + per_connection).min(chunks)))
+        .collect()
+}
+
+/// The seconds to wait if the error is a flood wait.
+fn flood_wait(error: &InvocationError) -> Option<u32> {
+    match error {
+        InvocationError::Rpc(rpc) if rpc.is("FLOOD_WAIT") => Some(rpc.value.unwrap_or(0)),
+        _ => None,
+    }
+}
+
+/// How long to sleep before retrying, if the error is a flood wait no longer than `max`.
+fn flood_wait_delay(error: &InvocationError, max: Duration) -> Option<Duration> {
+    let delay = Duration::from_secs(flood_wait(error)?.into());
+    if delay <= max {
+        Some(delay)
+    } else {
+        None
+    }
+}
+
+/// Pick the chunk size and the chunks to fetch so that they cover `limit` bytes starting at
+/// `offset`, returned as `(chunk_size, first_chunk, chunk_count)`.
+///
+/// The chunk size is the smallest valid one that fits the range, so that small ranges don't
+/// fetch much more than needed. Being a power of two, chunks never cross the 1 MiB boundaries
+/// that requests can't span.
+fn range_chunks(offset: u64, limit: u64) -> (u64, u64, u64) {
+    let chunk_size = limit
+        .next_power_of_two()
+        .clamp(MIN_CHUNK_SIZE as u64, MAX_CHUNK_SIZE as u64);
+    let first = offset / chunk_size;
+    let last = (offset + limit).div_ceil(chunk_size);
+    (chunk_size, first, last - first)
+}
+
+/// Keep the `limit` bytes of the fetched chunks starting `skip` bytes into them.
+fn trim_range(mut buffer: Vec<u8>, skip: u64, limit: u64) -> Vec<u8> {
+    let len = buffer.len() as u64;
+    let start = skip.min(len);
+    let end = start.saturating_add(limit).min(len);
+    buffer.truncate(end as usize);
+    buffer.drain(..start as usize);
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammers_mtproto::mtp::RpcError;
+
+    fn rpc_error(name: &str) -> InvocationError {
+        InvocationError::Rpc(RpcError {
+            code: 400,
+            name: name.to_string(),
+            value: None,
+            caused_by: None,
+        })
+    }
+
+    #[test]
+    fn sleep_through_short_flood_waits() {
+        let flood = |secs| {
+            InvocationError::Rpc(RpcError {
+                code: 420,
+                name: "FLOOD_WAIT".to_string(),
+                value: Some(secs),
+                caused_by: None,
+            })
         };
+        let max = Duration::from_secs(60);
 
-        let res = Ok(Some((lock.current_part, bytes)));
-        lock.current_part += 1;
-        res
+        assert_eq!(
+            flood_wait_delay(&flood(17), max),
+            Some(Duration::from_secs(17))
+        );
+        assert_eq!(flood_wait_delay(&flood(61), max), None);
+        assert_eq!(flood_wait_delay(&rpc_error("LIMIT_INVALID"), max), None);
+        assert!(matches!(
+            DownloadError::from(flood(61)),
+            DownloadError::FloodWait(61)
+        ));
+    }
+
+    #[test]
+    fn detect_expired_file_reference() {
+        assert!(matches!(
+            DownloadError::from(rpc_error("FILE_REFERENCE_EXPIRED")),
+            DownloadError::FileReferenceExpired
+        ));
+        assert!(matches!(
+            DownloadError::from(rpc_error("LIMIT_INVALID")),
+            DownloadError::Rpc(_)
+        ));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn read_chunks_for_upload() {
+        let chunks = |sizes: &[usize]| {
+            stream::iter(
+                sizes
+                    .iter()
+                    .map(|&n| Ok(Bytes::from(vec![1; n])))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let mut reader = ChunkReader::new(chunks(&[3, 3, 2]), 8);
+        let mut buffer = vec![0; 5];
+        reader.read_exact(&mut buffer).await.unwrap();
+        reader.read_exact(&mut buffer[..3]).await.unwrap();
+        assert!(reader.finish().await.is_ok());
+
+        let mut reader = ChunkReader::new(chunks(&[3, 3]), 8);
+        assert!(reader.read_exact(&mut buffer[..5]).await.is_ok());
+        assert!(reader.read_exact(&mut buffer[..3]).await.is_err());
+        assert!(matches!(
+            reader.error,
+            Some(DownloadError::SizeMismatch {
+                expected: 8,
+                got: 6
+            })
+        ));
+
+        let mut reader = ChunkReader::new(chunks(&[4, 4]), 4);
+        reader.read_exact(&mut buffer[..4]).await.unwrap();
+        assert!(matches!(
+            reader.finish().await,
+            Err(DownloadError::SizeMismatch {
+                expected: 4,
+                got: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn describe_download_errors() {
+        use std::error::Error as _;
+
+        let error = DownloadError::SizeMismatch {
+            expected: 1024,
+            got: 512,
+        };
+        assert_eq!(
+            error.to_string(),
+            "download error: expected 1024 bytes but got 512"
+        );
+        assert!(error.source().is_none());
+
+        let error = DownloadError::from(io::Error::new(io::ErrorKind::Other, "disk full"));
+        assert_eq!(error.to_string(), "download error: disk full");
+        assert!(error.source().is_some());
+
+        let error = DownloadError::NotConnected;
+        assert_eq!(error.to_string(), "download error: client is not connected");
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn check_download_chunk_size() {
+        let with_size = |chunk_size| DownloadOptions {
+            chunk_size,
+            ..Default::default()
+        };
+        assert!(DownloadOptions::default().check().is_ok());
+        assert!(with_size(4 * 1024).check().is_ok());
+        assert!(with_size(512 * 1024).check().is_ok());
+        assert!(with_size(1024 * 1024).check().is_ok());
+        assert!(with_size(0).check().is_err());
+        assert!(with_size(1024).check().is_err());
+        assert!(with_size(1000).check().is_err());
+        assert!(with_size(12 * 1024).check().is_err());
+        assert!(with_size(2 * 1024 * 1024).check().is_err());
+    }
+
+    #[test]
+    fn reject_zero_rate_limit() {
+        let options = DownloadOptions {
+            max_bytes_per_sec: Some(0),
+            ..Default::default()
+        };
+        assert!(options.check().is_err());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn limit_download_rate() {
+        let mut limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+        // The first second's worth is a burst, and the rest is paced.
+        limiter.consume(1000).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        limiter.consume(200).await;
+        limiter.consume(200).await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(350), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn share_cancellation_between_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        assert_eq!(token, clone);
+        assert_ne!(token, CancellationToken::new());
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn detect_truncated_download() {
+        assert!(check_size(Some(1024), 1024).is_ok());
+        assert!(check_size(None, 1000).is_ok());
+        assert!(matches!(
+            check_size(Some(1024), 1000),
+            Err(DownloadError::SizeMismatch {
+                expected: 1024,
+                got: 1000
+            })
+        ));
+    }
+
+    #[test]
+    fn split_parallel_download() {
+        assert_eq!(chunk_ranges(0, 1024, 4), vec![]);
+        assert_eq!(chunk_ranges(1000, 1024, 4), vec![(0, 1)]);
+        assert_eq!(
+            chunk_ranges(10 * 1024, 1024, 4),
+            vec![(0, 3), (3, 6), (6, 9), (9, 10)]
+        );
+        assert_eq!(chunk_ranges(8 * 1024 + 1, 1024, 0), vec![(0, 9)]);
+        assert_eq!(
+            chunk_ranges(100 * 1024, 1024, 1000).len(),
+            MAX_DOWNLOAD_CONNECTIONS
+        );
+    }
+
+    #[test]
+    fn cover_unaligned_range() {
+        // 100 bytes that straddle two 4 KiB chunks.
+        assert_eq!(range_chunks(4096 * 3 - 50, 100), (4096, 2, 2));
+        // Small ranges within a single chunk only need that chunk.
+        assert_eq!(range_chunks(5000, 1000), (4096, 1, 1));
+    }
+
+    #[test]
+    fn cover_range_with_many_requests() {
+        let chunk = MAX_CHUNK_SIZE as u64;
+        assert_eq!(range_chunks(10, 3 * chunk), (chunk, 0, 4));
+        assert_eq!(range_chunks(2 * chunk, 2 * chunk), (chunk, 2, 2));
+    }
+
+    #[test]
+    fn trim_range_past_end() {
+        let limit = i64::MAX as u64;
+        let (chunk_size, first, count) = range_chunks(10, limit);
+        assert_eq!((chunk_size, first), (MAX_CHUNK_SIZE as u64, 0));
+        assert!(count > 1);
+
+        // The file ends long before the limit, so only what was fetched is returned.
+        assert_eq!(
+            trim_range((0..20).collect(), 10, limit),
+            (10..20).collect::<Vec<u8>>()
+        );
+        assert_eq!(trim_range((0..20).collect(), 30, limit), Vec::<u8>::new());
+        assert_eq!(
+            trim_range((0..20).collect(), 10, 5),
+            vec![10, 11, 12, 13, 14]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn read_exactly_stream_size() {
+        let size = MAX_CHUNK_SIZE as usize + 10;
+        let mut stream = io::repeat(7).take(size as u64 + 5);
+        {
+            let parts = PartStream::new(&mut stream, size);
+            assert_eq!(parts.total_parts(), 2);
+
+            let (_, first) = parts.next_part().await.unwrap().unwrap();
+            let (_, last) = parts.next_part().await.unwrap().unwrap();
+            assert_eq!((first.len(), last.len()), (MAX_CHUNK_SIZE as usize, 10));
+            assert!(parts.next_part().await.unwrap().is_none());
+        }
+
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest.len(), 5);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn read_parts_concurrently() {
+        let size = 20 * 1024 * 1024 + 1;
+        let mut stream = io::repeat(7).take(size as u64);
+        let parts = PartStream::new(&mut stream, size);
+        assert_eq!(parts.total_parts(), 41);
+
+        let worker = || async {
+            let mut read = Vec::new();
+            while let Some((part, bytes)) = parts.next_part().await? {
+                read.push((part, bytes.len()));
+            }
+            Ok::<_, io::Error>(read)
+        };
+        let mut read = try_join_all((0..4).map(|_| worker()))
+            .await
+            .unwrap()
+            .concat();
+        read.sort_unstable();
+
+        assert_eq!(read.len(), 41);
+        assert!(read
+            .iter()
+            .enumerate()
+            .all(|(i, &(part, _))| part == i as i32));
+        assert_eq!(read.iter().map(|&(_, len)| len).sum::<usize>(), size);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn fail_on_short_stream() {
+        let mut stream = io::repeat(7).take(100);
+        let parts = PartStream::new(&mut stream, 200);
+        let err = parts.next_part().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn truncate_partial_file_for_resume() {
+        let path = std::env::temp_dir().join("grammers-truncate-partial-file.bin");
+        let len = |path: &Path| std::fs::metadata(path).unwrap().len();
+
+        // A trailing partial chunk is dropped, so that it can be downloaded again.
+        std::fs::write(&path, vec![1; 10_000]).unwrap();
+        let (_, offset) = open_for_resume(&path, 4096, Some(20_000)).await.unwrap();
+        assert_eq!((offset, len(&path)), (8192, 8192));
+
+        // A complete file is kept as-is, and there's nothing left to download.
+        std::fs::write(&path, vec![1; 20_000]).unwrap();
+        let (_, offset) = open_for_resume(&path, 4096, Some(20_000)).await.unwrap();
+        assert_eq!((offset, len(&path)), (20_000, 20_000));
+
+        // A file larger than the document can't be part of it.
+        std::fs::write(&path, vec![1; 30_000]).unwrap();
+        let (_, offset) = open_for_resume(&path, 4096, Some(20_000)).await.unwrap();
+        assert_eq!((offset, len(&path)), (0, 0));
+
+        std::fs::remove_file(&path).unwrap();
+        let (_, offset) = open_for_resume(&path, 4096, Some(20_000)).await.unwrap();
+        assert_eq!((offset, len(&path)), (0, 0));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resume_on_chunk_boundary() {
+        assert_eq!(resume_offset(0, 1024, Some(5000)), 0);
+        assert_eq!(resume_offset(2500, 1024, Some(5000)), 2048);
+        assert_eq!(resume_offset(2048, 1024, None), 2048);
+        assert_eq!(resume_offset(5000, 1024, Some(5000)), 5000);
+        assert_eq!(resume_offset(6000, 1024, Some(5000)), 0);
     }
 }
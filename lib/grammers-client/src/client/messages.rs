@@ -7,12 +7,77 @@
 // except according to those terms.
 
 //! Methods related to sending messages.
-use crate::types::{Chat, IterBuffer, Message};
+use crate::types::{Chat, IterBuffer, Media, Message};
 use crate::utils::{generate_random_id, generate_random_ids};
 use crate::{types, ChatMap, Client};
 pub use grammers_mtsender::{AuthorizationError, InvocationError};
 use grammers_tl_types as tl;
 use std::collections::HashMap;
+use std::fmt;
+
+/// The error type which is returned when sending an album fails.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SendAlbumError {
+    /// Albums must have between 2 and 10 messages, but this many were given.
+    InvalidLength(usize),
+    /// The message at this index has no media.
+    MissingMedia(usize),
+    /// The media of the message at this index was uploaded, but Telegram did not respond with
+    /// a photo or document that can be part of an album.
+    UnusableMedia(usize),
+    /// The request to upload or send the media failed.
+    Other(InvocationError),
+}
+
+impl fmt::Display for SendAlbumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SendAlbumError::*;
+        match self {
+            InvalidLength(n) => write!(
+                f,
+                "send album error: albums must have between 2 and 10 messages, got {}",
+                n
+            ),
+            MissingMedia(i) => write!(f, "send album error: message {} has no media", i),
+            UnusableMedia(i) => write!(
+                f,
+                "send album error: uploaded media of message {} can't be sent in an album",
+                i
+            ),
+            Other(e) => write!(f, "send album error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SendAlbumError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SendAlbumError::Other(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<InvocationError> for SendAlbumError {
+    fn from(error: InvocationError) -> Self {
+        Self::Other(error)
+    }
+}
+
+/// The media of every message in an album, if the album can be sent at all.
+fn album_media(
+    messages: &[types::InputMessage],
+) -> Result<Vec<tl::enums::InputMedia>, SendAlbumError> {
+    if !(2..=10).contains(&messages.len()) {
+        return Err(SendAlbumError::InvalidLength(messages.len()));
+    }
+    messages
+        .iter()
+        .enumerate()
+        .map(|(i, message)| message.media.clone().ok_or(SendAlbumError::MissingMedia(i)))
+        .collect()
+}
 
 fn map_random_ids_to_messages(
     client: &Client,
@@ -64,6 +129,29 @@ fn map_random_ids_to_messages(
     }
 }
 
+fn find_edited_message(
+    client: &Client,
+    message_id: i32,
+    updates: tl::enums::Updates,
+) -> Option<Message> {
+    let (updates, users, chats) = match updates {
+        tl::enums::Updates::Updates(u) => (u.updates, u.users, u.chats),
+        tl::enums::Updates::Combined(u) => (u.updates, u.users, u.chats),
+        _ => return None,
+    };
+    let chats = ChatMap::new(users, chats);
+
+    updates
+        .into_iter()
+        .filter_map(|update| match update {
+            tl::enums::Update::EditMessage(u) => Some(u.message),
+            tl::enums::Update::EditChannelMessage(u) => Some(u.message),
+            _ => None,
+        })
+        .filter_map(|message| Message::new(client, message, &chats))
+        .find(|message| message.msg.id == message_id)
+}
+
 const MAX_LIMIT: usize = 100;
 
 impl<R: tl::RemoteCall<Return = tl::enums::messages::Messages>> IterBuffer<R, Message> {
@@ -337,7 +425,8 @@ impl Client {
     /// This method can also be used to send media such as photos, videos, documents, polls, etc.
     ///
     /// If you want to send a local file as media, you will need to use
-    /// [`Client::upload_file`] first.
+    /// [`Client::upload_file`] first. The text of a message with media becomes its caption,
+    /// which keeps the same formatting entities as a text message would.
     ///
     /// Refer to [`InputMessage`] to learn more formatting options, such as using markdown or
     /// adding buttons under your message (if you're logged in as a bot).
@@ -415,6 +504,123 @@ impl Client {
         })
     }
 
+    /// Sends between 2 and 10 media messages to the desired chat, grouped as a single album.
+    ///
+    /// Every message must have media, such as a photo or a document, and its text is used as
+    /// the caption of that media. Options such as [`InputMessage::silent`] or
+    /// [`InputMessage::reply_to`] are taken from the first message. Files that have not been
+    /// sent before are uploaded to the chat first, as Telegram requires albums to consist of
+    /// media that already exists.
+    ///
+    /// Returns the new messages in a list, in the same order as the input. Those messages that
+    /// could not be sent will be `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::InputMessage;
+    ///
+    /// let first = client.upload_file("/home/username/photos/first.jpg").await?;
+    /// let second = client.upload_file("/home/username/photos/second.jpg").await?;
+    ///
+    /// client.send_album(&chat, vec![
+    ///     InputMessage::text("Our trip").photo(first),
+    ///     InputMessage::text("").photo(second),
+    /// ]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Fails with [`SendAlbumError::InvalidLength`] if there are fewer than 2 or more than 10
+    /// messages, and with [`SendAlbumError::MissingMedia`] if any of them has no media. Nothing
+    /// is uploaded in either case.
+    /// If Telegram's response to uploading a file can't be sent in an album, this fails with
+    /// [`SendAlbumError::UnusableMedia`] instead of sending the album.
+    ///
+    /// [`InputMessage::silent`]: crate::InputMessage::silent
+    /// [`InputMessage::reply_to`]: crate::InputMessage::reply_to
+    pub async fn send_album(
+        &self,
+        chat: &Chat,
+        messages: Vec<types::InputMessage>,
+    ) -> Result<Vec<Option<Message>>, SendAlbumError> {
+        let album = album_media(&messages)?;
+
+        let peer = chat.to_input_peer();
+        let random_ids = generate_random_ids(messages.len());
+        let mut multi_media = Vec::with_capacity(messages.len());
+        for (i, ((message, media), &random_id)) in messages
+            .iter()
+            .zip(album)
+            .zip(random_ids.iter())
+            .enumerate()
+        {
+            let media = self
+                .upload_album_media(&peer, media)
+                .await?
+                .ok_or(SendAlbumError::UnusableMedia(i))?;
+            multi_media.push(
+                tl::types::InputSingleMedia {
+                    media,
+                    random_id,
+                    message: message.text.clone(),
+                    entities: if message.entities.is_empty() {
+                        None
+                    } else {
+                        Some(message.entities.clone())
+                    },
+                }
+                .into(),
+            );
+        }
+
+        let first = &messages[0];
+        let updates = self
+            .invoke(&tl::functions::messages::SendMultiMedia {
+                silent: first.silent,
+                background: first.background,
+                clear_draft: first.clear_draft,
+                peer,
+                reply_to_msg_id: first.reply_to,
+                multi_media,
+                schedule_date: first.schedule_date,
+            })
+            .await?;
+        Ok(map_random_ids_to_messages(self, &random_ids, updates))
+    }
+
+    /// Albums can only contain media which exists in Telegram's servers, so freshly uploaded
+    /// files (or those at an external URL) need to be turned into a photo or document first.
+    ///
+    /// Returns `None` if Telegram's response to the upload can't be used as such media.
+    async fn upload_album_media(
+        &self,
+        peer: &tl::enums::InputPeer,
+        media: tl::enums::InputMedia,
+    ) -> Result<Option<tl::enums::InputMedia>, InvocationError> {
+        use tl::enums::InputMedia as IM;
+
+        match media {
+            IM::UploadedPhoto(_)
+            | IM::UploadedDocument(_)
+            | IM::PhotoExternal(_)
+            | IM::DocumentExternal(_) => {
+                let uploaded = self
+                    .invoke(&tl::functions::messages::UploadMedia {
+                        peer: peer.clone(),
+                        media,
+                    })
+                    .await?;
+                Ok(
+                    Media::from_raw(uploaded, self.clone())
+                        .and_then(|media| media.to_input_media()),
+                )
+            }
+            media => Ok(Some(media)),
+        }
+    }
+
     /// Edits an existing message.
     ///
     /// Similar to [`Client::send_message`], advanced formatting can be achieved with the
@@ -456,6 +662,49 @@ impl Client {
         Ok(())
     }
 
+    /// Replaces the media of an existing message, keeping its text (now caption) as-is.
+    ///
+    /// The new media can be existing media, obtained from [`Media::to_input_media`], or any
+    /// other raw input media, such as one referencing a freshly uploaded file.
+    ///
+    /// Returns the edited message, or `None` if Telegram did not include it in its response.
+    ///
+    /// Telegram can only replace media, not add it, so editing a message which had no media will
+    /// fail with a `MEDIA_PREV_INVALID` RPC error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, message: grammers_client::types::Message, mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let old_message_id = 123;
+    /// if let Some(media) = message.media().and_then(|media| media.to_input_media()) {
+    ///     client.edit_message_media(&chat, old_message_id, media).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn edit_message_media<M: Into<tl::enums::InputMedia>>(
+        &self,
+        chat: &Chat,
+        message_id: i32,
+        media: M,
+    ) -> Result<Option<Message>, InvocationError> {
+        let updates = self
+            .invoke(&tl::functions::messages::EditMessage {
+                no_webpage: false,
+                peer: chat.to_input_peer(),
+                id: message_id,
+                message: None,
+                media: Some(media.into()),
+                reply_markup: None,
+                entities: None,
+                schedule_date: None,
+            })
+            .await?;
+
+        Ok(find_edited_message(self, message_id, updates))
+    }
+
     /// Deletes up to 100 messages in a chat.
     ///
     /// <div class="stab unstable">
@@ -858,3 +1107,28 @@ impl Client {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InputMessage;
+
+    #[test]
+    fn check_album_media() {
+        let photo = || InputMessage::text("").photo_url("https://example.com/photo.jpg");
+
+        assert!(matches!(
+            album_media(&[photo()]),
+            Err(SendAlbumError::InvalidLength(1))
+        ));
+        assert!(matches!(
+            album_media(&(0..11).map(|_| photo()).collect::<Vec<_>>()),
+            Err(SendAlbumError::InvalidLength(11))
+        ));
+        assert!(matches!(
+            album_media(&[photo(), InputMessage::text("no media")]),
+            Err(SendAlbumError::MissingMedia(1))
+        ));
+        assert_eq!(album_media(&[photo(), photo()]).unwrap().len(), 2);
+    }
+}
@@ -18,3 +18,5 @@ pub mod updates;
 pub use auth::SignInError;
 pub(crate) use client::ClientInner;
 pub use client::{Client, Config, InitParams};
+pub use files::{CancellationToken, DownloadError, DownloadOptions, TransferError};
+pub use messages::SendAlbumError;
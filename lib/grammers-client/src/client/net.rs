@@ -5,17 +5,20 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+use super::client::ExportedSender;
 use super::{Client, ClientInner, Config};
 use crate::utils::{self, AsyncMutex, Mutex};
 use grammers_mtproto::mtp::{self};
 use grammers_mtproto::transport;
-use grammers_mtsender::{self as sender, AuthorizationError, InvocationError, Sender};
+use grammers_mtsender::{self as sender, AuthorizationError, InvocationError, ReadError, Sender};
 use grammers_session::{ChatHashCache, MessageBox};
 use grammers_tl_types::{self as tl, Deserializable};
 use log::info;
 use sender::Enqueuer;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::io;
 use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::oneshot::error::TryRecvError;
 use tokio::sync::Notify;
@@ -157,6 +160,8 @@ impl Client {
             last_update_limit_warn: Mutex::new("client.last_update_limit_warn", None),
             updates: Mutex::new("client.updates", updates),
             request_tx: Mutex::new("client.request_tx", request_tx),
+            exported_senders: Mutex::new("client.exported_senders", HashMap::new()),
+            connected: AtomicBool::new(true),
         }));
 
         // Don't bother getting pristine state if we're not logged in.
@@ -232,6 +237,86 @@ impl Client {
         }
     }
 
+    /// Invoke a request in the given data center, which may not be the one the client is
+    /// connected to.
+    ///
+    /// Connections to other data centers are made (and logged in by exporting the current
+    /// authorization) the first time they're needed, and are reused afterwards. If a connection
+    /// fails with an I/O error, it is dropped, and the next request connects again.
+    ///
+    /// There is a single connection per data center, which is locked for the entire round trip
+    /// of the request. This means requests to the same data center other than the client's run
+    /// one after the other, even if they are made from several tasks at once.
+    pub(crate) async fn invoke_in_dc<R: tl::RemoteCall>(
+        &self,
+        request: &R,
+        dc_id: i32,
+    ) -> Result<R::Return, InvocationError> {
+        if dc_id == *self.0.dc_id.lock("client.invoke_in_dc") {
+            return self.invoke(request).await;
+        }
+
+        let sender = self.exported_sender(dc_id).await?;
+        let result = sender
+            .lock("client.invoke_in_dc")
+            .await
+            .invoke(request)
+            .await;
+        if let Err(InvocationError::Read(ReadError::Io(_))) = result {
+            self.drop_exported_sender(dc_id, &sender);
+        }
+        Ok(R::Return::from_bytes(&result?)?)
+    }
+
+    /// Forget the connection to the data center, unless it was already replaced by another.
+    fn drop_exported_sender(&self, dc_id: i32, sender: &ExportedSender) {
+        let mut senders = self.0.exported_senders.lock("client.drop_exported_sender");
+        if matches!(senders.get(&dc_id), Some(current) if Arc::ptr_eq(current, sender)) {
+            senders.remove(&dc_id);
+        }
+    }
+
+    async fn exported_sender(&self, dc_id: i32) -> Result<ExportedSender, InvocationError> {
+        if let Some(sender) = self
+            .0
+            .exported_senders
+            .lock("client.exported_sender")
+            .get(&dc_id)
+        {
+            return Ok(Arc::clone(sender));
+        }
+
+        let (mut sender, _) = connect_sender(dc_id, &self.0.config)
+            .await
+            .map_err(|e| match e {
+                AuthorizationError::Invoke(e) => e,
+                // To the caller, failing to generate the key is like any other failed connection.
+                AuthorizationError::Gen(e) => {
+                    ReadError::Io(io::Error::new(io::ErrorKind::Other, e)).into()
+                }
+            })?;
+
+        let tl::enums::auth::ExportedAuthorization::Authorization(exported) = self
+            .invoke(&tl::functions::auth::ExportAuthorization { dc_id })
+            .await?;
+        sender
+            .invoke(&tl::functions::auth::ImportAuthorization {
+                id: exported.id,
+                bytes: exported.bytes,
+            })
+            .await?;
+
+        // Someone else may have connected in the meantime, in which case theirs is kept.
+        let sender = Arc::new(AsyncMutex::new("client.exported_sender", sender));
+        Ok(Arc::clone(
+            self.0
+                .exported_senders
+                .lock("client.exported_sender")
+                .entry(dc_id)
+                .or_insert(sender),
+        ))
+    }
+
     /// Perform a single network step.
     ///
     /// Most commonly, you will want to use the higher-level abstraction [`Client::next_update`]
@@ -252,7 +337,12 @@ impl Client {
         match self.0.sender.try_lock("client.step") {
             Ok(mut sender) => {
                 // Sender was unlocked, we're the ones that will perform the network step.
-                let updates = sender.step().await?;
+                let updates = sender.step().await.map_err(|e| {
+                    if let ReadError::Io(_) = e {
+                        self.0.connected.store(false, Ordering::SeqCst);
+                    }
+                    e
+                })?;
                 self.0.stepping_done.notify_waiters();
                 self.process_socket_updates(updates);
 
@@ -270,6 +360,27 @@ impl Client {
         }
     }
 
+    /// Whether the client is still connected to Telegram.
+    ///
+    /// The client is connected until reading from the network fails, for example because the
+    /// server closed the connection. After that, requests won't succeed, so this can be used
+    /// to fail early instead. Downloads do so with [`DownloadError::NotConnected`].
+    ///
+    /// [`DownloadError::NotConnected`]: crate::DownloadError::NotConnected
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) {
+    /// if !client.is_connected() {
+    ///     println!("Lost the connection to Telegram");
+    /// }
+    /// # }
+    /// ```
+    pub fn is_connected(&self) -> bool {
+        self.0.connected.load(Ordering::SeqCst)
+    }
+
     /// Run the client by repeatedly calling [`Client::step`] until a graceful disconnection
     /// occurs, or a network error occurs. Incoming updates are ignored and simply dropped.
     /// instead.
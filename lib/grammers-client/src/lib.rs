@@ -41,5 +41,8 @@ mod parsers;
 pub mod types;
 pub(crate) mod utils;
 
-pub use client::{Client, Config, InitParams, SignInError};
+pub use client::{
+    CancellationToken, Client, Config, DownloadError, DownloadOptions, InitParams, SendAlbumError,
+    SignInError, TransferError,
+};
 pub use types::{button, reply_markup, ChatMap, InputMessage, Update};
@@ -8,16 +8,28 @@
 use grammers_tl_types as tl;
 use std::{convert::TryInto, time::Duration};
 
+/// Extra information about an uploaded document, used by clients to display it correctly.
+///
+/// Refer to [`InputMessage::attribute`](crate::InputMessage::attribute) to learn how to use
+/// them.
 pub enum Attribute {
+    /// The document is a song or some other audio file.
     Audio {
         duration: Duration,
         title: Option<String>,
         performer: Option<String>,
     },
+    /// The document is a voice note, with an optional waveform to show while it's not playing.
+    ///
+    /// The waveform must already be packed, as done by [`pack_waveform`].
     Voice {
         duration: Duration,
         waveform: Option<Vec<u8>>,
     },
+    /// The document is a video of the given width (`w`) and height (`h`), in pixels.
+    ///
+    /// Round messages are shown as video notes, and videos supporting streaming can be played
+    /// before they are fully downloaded.
     Video {
         round_message: bool,
         supports_streaming: bool,
@@ -25,6 +37,7 @@ pub enum Attribute {
         w: i32,
         h: i32,
     },
+    /// The name the document should be downloaded with.
     FileName(String),
 }
 
@@ -44,7 +57,7 @@ impl From<Attribute> for tl::enums::DocumentAttribute {
                 waveform: None,
             }),
             Voice { duration, waveform } => Self::Audio(tl::types::DocumentAttributeAudio {
-                voice: false,
+                voice: true,
                 duration: duration.as_secs().try_into().unwrap(),
                 title: None,
                 performer: None,
@@ -69,3 +82,49 @@ impl From<Attribute> for tl::enums::DocumentAttribute {
         }
     }
 }
+
+/// Pack the amplitudes of a voice note into the waveform Telegram expects.
+///
+/// Each amplitude uses 5 bits, so values above 31 are clamped. Official clients use around 100
+/// amplitudes, spread evenly over the duration of the voice note.
+pub fn pack_waveform(amplitudes: &[u8]) -> Vec<u8> {
+    let mut packed = vec![0; (amplitudes.len() * 5).div_ceil(8)];
+    for (i, &amplitude) in amplitudes.iter().enumerate() {
+        let value = (amplitude.min(31) as u16) << (i * 5 % 8);
+        let byte = i * 5 / 8;
+        packed[byte] |= value as u8;
+        if let Some(next) = packed.get_mut(byte + 1) {
+            *next |= (value >> 8) as u8;
+        }
+    }
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_five_bit_amplitudes() {
+        assert_eq!(pack_waveform(&[]), Vec::<u8>::new());
+        assert_eq!(pack_waveform(&[31]), vec![0b0001_1111]);
+        assert_eq!(pack_waveform(&[1, 2]), vec![0b0100_0001, 0b0000_0000]);
+        assert_eq!(pack_waveform(&[31; 8]), vec![0xff; 5]);
+        assert_eq!(pack_waveform(&[255]), pack_waveform(&[31]));
+    }
+
+    #[test]
+    fn voice_attribute_is_voice() {
+        let attr = tl::enums::DocumentAttribute::from(Attribute::Voice {
+            duration: Duration::from_secs(3),
+            waveform: None,
+        });
+        match attr {
+            tl::enums::DocumentAttribute::Audio(audio) => {
+                assert!(audio.voice);
+                assert_eq!(audio.duration, 3);
+            }
+            _ => panic!("expected an audio attribute"),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::types::media::{Document, Photo};
+use crate::types::photo_sizes::PhotoSize;
+use crate::DownloadError;
+use futures_util::future::BoxFuture;
+use std::path::Path;
+
+/// Anything that can be saved to a file, no matter where its contents come from.
+///
+/// This lets generic code (like a download manager) work with `&dyn Downloadable` instead of
+/// handling photos, documents and thumbnails separately.
+pub trait Downloadable {
+    /// Download the contents into the file at the given path, creating or truncating it.
+    fn download<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), DownloadError>>;
+
+    /// How many bytes will be downloaded, if known beforehand.
+    fn size_hint(&self) -> Option<i64>;
+}
+
+impl Downloadable for PhotoSize {
+    fn download<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), DownloadError>> {
+        Box::pin(PhotoSize::download(self, path))
+    }
+
+    fn size_hint(&self) -> Option<i64> {
+        match self.size() {
+            0 => None,
+            size => Some(size as i64),
+        }
+    }
+}
+
+impl Downloadable for Document {
+    fn download<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), DownloadError>> {
+        Box::pin(Document::download(self, path))
+    }
+
+    fn size_hint(&self) -> Option<i64> {
+        self.size()
+    }
+}
+
+impl Downloadable for Photo {
+    fn download<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<(), DownloadError>> {
+        Box::pin(Photo::download(self, path))
+    }
+
+    fn size_hint(&self) -> Option<i64> {
+        self.largest_size().and_then(|size| size.size_hint())
+    }
+}
@@ -5,10 +5,11 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use super::attributes::Attribute;
+use super::attributes::{pack_waveform, Attribute};
 use crate::types::{Media, ReplyMarkup, Uploaded};
 use grammers_tl_types as tl;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::mem;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // https://github.com/telegramdesktop/tdesktop/blob/e7fbcce9d9f0a8944eb2c34e74bd01b8776cb891/Telegram/SourceFiles/data/data_scheduled_messages.h#L52
 const SCHEDULE_ONCE_ONLINE: i32 = 0x7FFFFFFE;
@@ -175,6 +176,40 @@ impl InputMessage {
         self
     }
 
+    /// Include the uploaded file as a voice note in the message.
+    ///
+    /// The file should be audio encoded with Opus inside an OGG container, which is what
+    /// official clients record. The `waveform` is shown before the voice note is played, and it
+    /// must be packed with [`pack_waveform`]. If it's `None`, a flat waveform is used.
+    ///
+    /// The text will be the caption of the voice note, which may be empty for no caption.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: &mut grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    /// use grammers_client::{types::attributes::pack_waveform, InputMessage};
+    ///
+    /// let voice = client.upload_file("note.ogg").await?;
+    /// let waveform = pack_waveform(&[0, 8, 16, 31, 16, 8, 0]);
+    /// let message = InputMessage::text("").voice_note(voice, Duration::from_secs(7), Some(waveform));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn voice_note(self, file: Uploaded, duration: Duration, waveform: Option<Vec<u8>>) -> Self {
+        let mut message = self.document(file);
+        if message.mime_type.is_none() {
+            if let Some(tl::enums::InputMedia::UploadedDocument(document)) = &mut message.media {
+                document.mime_type = "audio/ogg".to_string();
+            }
+        }
+        message.attribute(Attribute::Voice {
+            duration,
+            waveform: Some(waveform.unwrap_or_else(|| pack_waveform(&[0; 100]))),
+        })
+    }
+
     /// Include an external file as a document in the message.
     ///
     /// You can use this to send videos, stickers, audios, or uncompressed photos.
@@ -195,7 +230,9 @@ impl InputMessage {
 
     /// Add additional attributes to the message.
     ///
-    /// This must be called *after* setting a file.
+    /// This must be called *after* setting a file. Attributes replace any previous attribute of
+    /// the same kind, so an [`Attribute::FileName`] can be used to change the name the file was
+    /// uploaded with. Audio and voice attributes are the same kind, as are the video ones.
     ///
     /// # Examples
     ///
@@ -219,18 +256,49 @@ impl InputMessage {
     pub fn attribute(mut self, attr: Attribute) -> Self {
         match &mut self.media {
             Some(tl::enums::InputMedia::UploadedDocument(document)) => {
-                document.attributes.push(attr.into());
+                let attr = tl::enums::DocumentAttribute::from(attr);
+                let kind = mem::discriminant(&attr);
+                document
+                    .attributes
+                    .retain(|existing| mem::discriminant(existing) != kind);
+                document.attributes.push(attr);
             }
             _ => {}
         }
         self
     }
 
+    /// Use the uploaded image as the thumbnail of the document.
+    ///
+    /// This must be called *after* setting a file. Without a thumbnail, clients will show no
+    /// preview for files such as videos, until the user downloads them. Telegram expects a JPEG
+    /// image no larger than 200 KB, and no bigger than 320x320 pixels.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: &mut grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::InputMessage;
+    ///
+    /// let video = client.upload_file("holidays.mp4").await?;
+    /// let thumb = client.upload_file("holidays.jpg").await?;
+    /// let message = InputMessage::text("").document(video).thumbnail(thumb);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn thumbnail(mut self, thumb: Uploaded) -> Self {
+        if let Some(tl::enums::InputMedia::UploadedDocument(document)) = &mut self.media {
+            document.thumb = Some(thumb.input_file);
+        }
+        self
+    }
+
     /// Copy media from an existing message.
     ///
-    /// You can use this to send media from another message without re-uploading it.
+    /// You can use this to send media from another message without re-uploading it. Media
+    /// which can't be sent again (see [`Media::to_input_media`]) is left out of the message.
     pub fn copy_media(mut self, media: &Media) -> Self {
-        self.media = Some(media.to_input_media());
+        self.media = media.to_input_media();
         self
     }
 
@@ -349,3 +417,106 @@ impl From<String> for InputMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uploaded(name: &str) -> Uploaded {
+        Uploaded::from_raw(
+            tl::types::InputFile {
+                id: 1,
+                parts: 1,
+                name: name.to_string(),
+                md5_checksum: String::new(),
+            }
+            .into(),
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "markdown")]
+    fn formatted_caption() {
+        let message = InputMessage::markdown("**Our** trip").photo(uploaded("trip.jpg"));
+        assert_eq!(message.text, "Our trip");
+        assert_eq!(
+            message.entities,
+            vec![tl::types::MessageEntityBold {
+                offset: 0,
+                length: 3
+            }
+            .into()]
+        );
+        assert!(message.media.is_some());
+    }
+
+    #[test]
+    fn document_with_thumbnail() {
+        let message = InputMessage::text("")
+            .document(uploaded("clip.mp4"))
+            .thumbnail(uploaded("clip.jpg"));
+        match message.media {
+            Some(tl::enums::InputMedia::UploadedDocument(document)) => {
+                assert_eq!(document.mime_type, "video/mp4");
+                assert_eq!(document.thumb, Some(uploaded("clip.jpg").input_file));
+            }
+            _ => panic!("expected an uploaded document"),
+        }
+    }
+
+    #[test]
+    fn replace_attributes_of_same_kind() {
+        let message = InputMessage::text("")
+            .document(uploaded("a"))
+            .attribute(Attribute::FileName("clip.mp4".to_string()))
+            .attribute(Attribute::Video {
+                round_message: false,
+                supports_streaming: true,
+                duration: Duration::from_secs(12),
+                w: 640,
+                h: 480,
+            });
+        match message.media {
+            Some(tl::enums::InputMedia::UploadedDocument(document)) => {
+                assert_eq!(document.attributes.len(), 2);
+                assert_eq!(
+                    document.attributes[0],
+                    tl::types::DocumentAttributeFilename {
+                        file_name: "clip.mp4".to_string()
+                    }
+                    .into()
+                );
+                assert!(matches!(
+                    document.attributes[1],
+                    tl::enums::DocumentAttribute::Video(_)
+                ));
+            }
+            _ => panic!("expected an uploaded document"),
+        }
+    }
+
+    #[test]
+    fn voice_note_with_flat_waveform() {
+        let message =
+            InputMessage::text("").voice_note(uploaded("a"), Duration::from_secs(5), None);
+        match message.media {
+            Some(tl::enums::InputMedia::UploadedDocument(document)) => {
+                assert_eq!(document.mime_type, "audio/ogg");
+                match &document.attributes[1] {
+                    tl::enums::DocumentAttribute::Audio(audio) => {
+                        assert!(audio.voice);
+                        assert_eq!(audio.waveform.as_ref().map(Vec::len), Some(63));
+                    }
+                    _ => panic!("expected an audio attribute"),
+                }
+            }
+            _ => panic!("expected an uploaded document"),
+        }
+    }
+
+    #[test]
+    fn thumbnail_without_document() {
+        let message = InputMessage::text("").thumbnail(uploaded("clip.jpg"));
+        assert!(message.media.is_none());
+    }
+}
@@ -5,32 +5,52 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use crate::types::photo_sizes::PhotoSize;
-use crate::Client;
+use crate::client::files::attached;
+use crate::types::photo_sizes::{PhotoSize, ThumbOwner, VideoSize};
+use crate::types::{Attribute, Downloadable};
+use crate::{CancellationToken, Client, DownloadError, DownloadOptions};
+use bytes::Bytes;
 use chrono::{DateTime, NaiveDateTime, Utc};
+use futures_util::future::Either;
+use futures_util::stream::{self, Stream};
+use grammers_mtsender::InvocationError;
 use grammers_tl_types as tl;
-use std::fmt::Debug;
-use std::path::Path;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWrite;
 
-#[derive(Clone, Debug, PartialEq)]
+/// Two photos are equal if they refer to the same photo stored in Telegram's servers, even if
+/// their file references or attached clients differ.
+#[derive(Clone, Debug)]
 pub struct Photo {
     photo: tl::types::MessageMediaPhoto,
-    client: Client,
+    client: Option<Client>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Two locations are equal if their coordinates have the same bits, so that a `NaN` coordinate
+/// is still equal to itself, even if their attached clients differ.
+#[derive(Clone, Debug)]
 pub struct Geo {
     geo: tl::types::MessageMediaGeo,
-    client: Client,
+    client: Option<Client>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Two live locations are equal if all of their fields are, with coordinates compared by their
+/// bits like in [`Geo`], even if their attached clients differ.
+#[derive(Clone, Debug)]
 pub struct GeoLive {
     geo: tl::types::MessageMediaGeoLive,
-    client: Client,
+    client: Option<Client>,
 }
 
+/// Mean radius of the Earth, in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct GeoPoint {
     pub longitude: f64,
@@ -38,10 +58,12 @@ pub struct GeoPoint {
     pub accuracy_radius: Option<i32>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Two documents are equal if they refer to the same file stored in Telegram's servers, even if
+/// their file references or attached clients differ.
+#[derive(Clone, Debug)]
 pub struct Document {
     document: tl::types::MessageMediaDocument,
-    client: Client,
+    client: Option<Client>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -51,14 +73,96 @@ pub struct Sticker {
     animated: bool,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Two uploaded files are equal if they are the same upload, which is identified by the random
+/// file identifier chosen when uploading.
+#[derive(Clone)]
 pub struct Uploaded {
     pub(crate) input_file: tl::enums::InputFile,
+    size: Option<i64>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Two contacts are equal if all of their fields are, even if their attached clients differ.
+#[derive(Clone, Debug)]
 pub struct Contact {
     contact: tl::types::MessageMediaContact,
+    client: Option<Client>,
+}
+
+/// Two web pages are equal if they have the same identifier, even if their previews or attached
+/// clients differ.
+#[derive(Clone, Debug)]
+pub struct WebPage {
+    // Boxed because full previews are much larger than any other media.
+    webpage: Box<tl::types::MessageMediaWebPage>,
+    client: Option<Client>,
+}
+
+/// Two polls are equal if their questions, answers and results are, even if they belong to
+/// different messages or their attached clients differ.
+#[derive(Clone, Debug)]
+pub struct Poll {
+    poll: tl::types::MessageMediaPoll,
+    client: Option<Client>,
+    // The chat and identifier of the message containing the poll, needed to vote.
+    message: Option<(tl::enums::InputPeer, i32)>,
+}
+
+/// One of the answers a poll can be voted with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PollAnswer {
+    /// The text shown to the user.
+    pub text: String,
+    /// The opaque key identifying this answer, which is what gets sent when voting.
+    pub option: Vec<u8>,
+}
+
+/// How many people voted for a given poll answer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PollAnswerVoters {
+    /// The key of the answer, matching [`PollAnswer::option`].
+    pub option: Vec<u8>,
+    /// How many people voted for this answer.
+    pub voters: i32,
+    /// Whether the current user voted for this answer.
+    pub chosen: bool,
+    /// Whether this is the correct answer of a quiz.
+    pub correct: bool,
+}
+
+/// The results of a poll, as far as they're known to the current user.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PollResults {
+    /// Votes per answer. Empty if the results are not visible yet, for example, because the
+    /// current user has not voted.
+    pub answers: Vec<PollAnswerVoters>,
+    /// How many people voted in total, if known.
+    pub total_voters: Option<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dice {
+    dice: tl::types::MessageMediaDice,
+}
+
+/// Two venues are equal if all of their fields are, with coordinates compared by their bits like
+/// in [`Geo`].
+#[derive(Clone, Debug)]
+pub struct Venue {
+    venue: tl::types::MessageMediaVenue,
+}
+
+/// Two invoices are equal if all of their fields are, with their photos compared by URL.
+#[derive(Clone, Debug)]
+pub struct Invoice {
+    invoice: tl::types::MessageMediaInvoice,
+}
+
+/// Two games are equal if they refer to the same game stored in Telegram's servers, even if
+/// their attached clients differ.
+#[derive(Clone, Debug)]
+pub struct Game {
+    game: tl::types::MessageMediaGame,
+    client: Option<Client>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -70,10 +174,64 @@ pub enum Media {
     Contact(Contact),
     Geo(Geo),
     GeoLive(GeoLive),
+    WebPage(WebPage),
+    Poll(Poll),
+    Dice(Dice),
+    Venue(Venue),
+    Invoice(Invoice),
+    Game(Game),
+    /// Media that this version of the library does not know how to handle, most likely
+    /// because it was introduced in a newer layer of Telegram's API.
+    Unsupported,
+}
+
+/// The kind of a [`Media`], without any of its data.
+///
+/// Its `Display` is a lowercase name of the kind, such as `live geo`, meant for logs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MediaKind {
+    Photo,
+    Document,
+    Sticker,
+    Contact,
+    Geo,
+    GeoLive,
+    WebPage,
+    Poll,
+    Dice,
+    Venue,
+    Invoice,
+    Game,
+    Unsupported,
+}
+
+impl fmt::Display for MediaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MediaKind::Photo => "photo",
+            MediaKind::Document => "document",
+            MediaKind::Sticker => "sticker",
+            MediaKind::Contact => "contact",
+            MediaKind::Geo => "geo",
+            MediaKind::GeoLive => "live geo",
+            MediaKind::WebPage => "web page",
+            MediaKind::Poll => "poll",
+            MediaKind::Dice => "dice",
+            MediaKind::Venue => "venue",
+            MediaKind::Invoice => "invoice",
+            MediaKind::Game => "game",
+            MediaKind::Unsupported => "unsupported",
+        })
+    }
 }
 
 impl Photo {
     pub(crate) fn from_raw(photo: tl::enums::Photo, client: Client) -> Self {
+        Self::from_photo(photo, Some(client))
+    }
+
+    fn from_photo(photo: tl::enums::Photo, client: Option<Client>) -> Self {
         Self {
             photo: tl::types::MessageMediaPhoto {
                 photo: Some(photo),
@@ -83,10 +241,34 @@ impl Photo {
         }
     }
 
-    pub(crate) fn from_media(photo: tl::types::MessageMediaPhoto, client: Client) -> Self {
+    fn from_media(photo: tl::types::MessageMediaPhoto, client: Option<Client>) -> Self {
         Self { photo, client }
     }
 
+    /// Attach the client used to download the photo, replacing the previous one (if any).
+    ///
+    /// This is needed to download photos which were deserialized, as they are detached.
+    pub fn set_client(&mut self, client: Client) {
+        self.client = Some(client);
+    }
+
+    /// Like [`Photo::set_client`], but taking and returning the photo to allow chaining.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.set_client(client);
+        self
+    }
+
+    /// The identifier and access hash of the photo, which stay the same for as long as the
+    /// photo exists, unlike its file reference.
+    fn identity(&self) -> Option<(i64, i64)> {
+        use tl::enums::Photo as P;
+
+        match self.photo.photo.as_ref()? {
+            P::Empty(photo) => Some((photo.id, 0)),
+            P::Photo(photo) => Some((photo.id, photo.access_hash)),
+        }
+    }
+
     fn to_input_location(&self) -> Option<tl::enums::InputFileLocation> {
         use tl::enums::Photo as P;
 
@@ -104,23 +286,18 @@ impl Photo {
         })
     }
 
-    fn to_input_media(&self) -> tl::types::InputMediaPhoto {
-        use tl::{
-            enums::{InputPhoto as eInputPhoto, Photo},
-            types::InputPhoto,
-        };
-
-        tl::types::InputMediaPhoto {
-            id: match self.photo.photo {
-                Some(Photo::Photo(ref photo)) => InputPhoto {
+    fn to_input_media(&self) -> Option<tl::types::InputMediaPhoto> {
+        match self.photo.photo {
+            Some(tl::enums::Photo::Photo(ref photo)) => Some(tl::types::InputMediaPhoto {
+                id: tl::types::InputPhoto {
                     id: photo.id,
                     access_hash: photo.access_hash,
                     file_reference: photo.file_reference.clone(),
                 }
                 .into(),
-                _ => eInputPhoto::Empty,
-            },
-            ttl_seconds: self.photo.ttl_seconds,
+                ttl_seconds: self.photo.ttl_seconds,
+            }),
+            _ => None,
         }
     }
 
@@ -136,6 +313,119 @@ impl Photo {
         }
     }
 
+    /// The identifier of the data center where the photo is stored, or `None` if the photo is
+    /// empty.
+    pub fn dc_id(&self) -> Option<i32> {
+        match self.photo.photo.as_ref()? {
+            tl::enums::Photo::Photo(photo) => Some(photo.dc_id),
+            tl::enums::Photo::Empty(_) => None,
+        }
+    }
+
+    /// The date on which the photo was uploaded, as a Unix timestamp, or `None` if the photo is
+    /// empty.
+    pub fn date(&self) -> Option<i32> {
+        match self.photo.photo.as_ref()? {
+            tl::enums::Photo::Photo(photo) => Some(photo.date),
+            tl::enums::Photo::Empty(_) => None,
+        }
+    }
+
+    /// Get the video versions of the photo, such as the animated loop of a video profile photo.
+    ///
+    /// Returns an empty vector if the photo is empty or has no video sizes.
+    pub fn video_sizes(&self) -> Vec<VideoSize> {
+        match self.photo.photo.as_ref() {
+            Some(tl::enums::Photo::Photo(photo)) => {
+                let owner = ThumbOwner::from_photo(photo);
+                photo
+                    .video_sizes
+                    .as_ref()
+                    .map(|sizes| {
+                        sizes
+                            .iter()
+                            .map(|x| VideoSize::make_from(x, &owner, self.client.clone()))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Whether the photo has stickers attached to it, or `false` if the photo is empty.
+    pub fn has_stickers(&self) -> bool {
+        match self.photo.photo.as_ref() {
+            Some(tl::enums::Photo::Photo(photo)) => photo.has_stickers,
+            _ => false,
+        }
+    }
+
+    /// The extension to use when saving the photo to disk, which is always `"jpg"`.
+    ///
+    /// Telegram re-encodes every photo as JPEG, regardless of the format it was uploaded in.
+    pub fn extension(&self) -> &'static str {
+        "jpg"
+    }
+
+    /// For how many seconds the photo can be seen after it's opened, if it self-destructs.
+    pub fn ttl_seconds(&self) -> Option<i32> {
+        self.photo.ttl_seconds
+    }
+
+    /// Download the largest size of the photo into the file at the given path.
+    ///
+    /// Returns [`DownloadError::NoSize`] if the photo has no sizes to download.
+    pub async fn download(&self, path: &Path) -> Result<(), DownloadError> {
+        self.largest_size()
+            .ok_or(DownloadError::NoSize)?
+            .download(path)
+            .await
+    }
+
+    /// Download the size of the photo with the given type into the file at the given path.
+    ///
+    /// If `thumb_type` is empty, the largest size is downloaded, like [`Photo::download`] does.
+    /// Returns [`DownloadError::NoSize`] if the photo has no sizes, or
+    /// [`DownloadError::UnknownThumbType`] if it has none of the given type. Either is reported
+    /// before making any request.
+    pub async fn download_thumb(&self, path: &Path, thumb_type: &str) -> Result<(), DownloadError> {
+        select_thumb(self.thumbs(), thumb_type)?
+            .download(path)
+            .await
+    }
+
+    /// Download the largest size of the photo into the file at the given path, returning the
+    /// path written.
+    ///
+    /// If the path is an existing directory, the photo is saved inside of it, named after its
+    /// identifier (such as `5012345678901234567.jpg`). If a file with the same name already
+    /// exists, a numeric suffix is added instead of overwriting it, like
+    /// [`Document::download_to_dir`] does. Otherwise, the path is returned as-is after
+    /// downloading into it like [`Photo::download`].
+    pub async fn download_returning_path(
+        &self,
+        dir_or_path: &Path,
+    ) -> Result<PathBuf, DownloadError> {
+        if is_dir(dir_or_path).await {
+            let name = match self.id() {
+                Some(id) => format!("{}.{}", id, self.extension()),
+                None => format!("photo.{}", self.extension()),
+            };
+            self.largest_size()
+                .ok_or(DownloadError::NoSize)?
+                .check_downloadable()?;
+            download_to_new_file(self, dir_or_path, &name).await
+        } else {
+            self.download(dir_or_path).await?;
+            Ok(dir_or_path.to_path_buf())
+        }
+    }
+
+    pub(crate) fn largest_size(&self) -> Option<PhotoSize> {
+        largest_thumb(self.thumbs())
+    }
+
     /// Get photo thumbs.
     ///
     /// Since Telegram doesn't store the original photo, it can be presented in different sizes
@@ -156,20 +446,78 @@ impl Photo {
 
         match photo {
             P::Empty(_) => vec![],
-            P::Photo(photo) => photo
-                .sizes
-                .iter()
-                .map(|x| PhotoSize::make_from(&x, &photo, self.client.clone()))
-                .collect(),
+            P::Photo(photo) => {
+                let owner = ThumbOwner::from_photo(photo);
+                photo
+                    .sizes
+                    .iter()
+                    .map(|x| PhotoSize::make_from(x, &owner, self.client.clone()))
+                    .collect()
+            }
         }
     }
 }
 
 impl Document {
-    pub(crate) fn from_media(document: tl::types::MessageMediaDocument, client: Client) -> Self {
+    fn from_media(document: tl::types::MessageMediaDocument, client: Option<Client>) -> Self {
         Self { document, client }
     }
 
+    /// Attach the client used to download the document, replacing the previous one (if any).
+    ///
+    /// This is needed to download documents which were deserialized, as they are detached.
+    pub fn set_client(&mut self, client: Client) {
+        self.client = Some(client);
+    }
+
+    /// Like [`Document::set_client`], but taking and returning the document to allow chaining.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.set_client(client);
+        self
+    }
+
+    /// The identifier and access hash of the document, which stay the same for as long as the
+    /// document exists, unlike its file reference.
+    fn identity(&self) -> Option<(i64, i64)> {
+        use tl::enums::Document as D;
+
+        match self.document.document.as_ref()? {
+            D::Empty(document) => Some((document.id, 0)),
+            D::Document(document) => Some((document.id, document.access_hash)),
+        }
+    }
+
+    fn raw(&self) -> Option<&tl::types::Document> {
+        match self.document.document.as_ref() {
+            Some(tl::enums::Document::Document(document)) => Some(document),
+            _ => None,
+        }
+    }
+
+    /// Get the document thumbnails, if any.
+    ///
+    /// Documents such as videos, stickers or images sent as files may have a server-generated
+    /// preview. The returned sizes work the same way as those of [`Photo::thumbs`]. Returns an
+    /// empty vector if the document is empty or has no thumbnails.
+    pub fn thumbs(&self) -> Vec<PhotoSize> {
+        let document = match self.raw() {
+            Some(document) => document,
+            None => return vec![],
+        };
+
+        let owner = ThumbOwner::from_document(document);
+        document
+            .thumbs
+            .as_ref()
+            .map(|thumbs| {
+                thumbs
+                    .iter()
+                    .map(|x| PhotoSize::make_from(x, &owner, self.client.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     fn to_input_location(&self) -> Option<tl::enums::InputFileLocation> {
         use tl::enums::Document as D;
 
@@ -187,25 +535,18 @@ impl Document {
         })
     }
 
-    fn to_input_media(&self) -> tl::types::InputMediaDocument {
-        use tl::{
-            enums::{Document, InputDocument as eInputDocument},
-            types::InputDocument,
-        };
-
-        tl::types::InputMediaDocument {
-            id: match self.document.document {
-                Some(Document::Document(ref document)) => InputDocument {
-                    id: document.id,
-                    access_hash: document.access_hash,
-                    file_reference: document.file_reference.clone(),
-                }
-                .into(),
-                _ => eInputDocument::Empty,
-            },
+    fn to_input_media(&self) -> Option<tl::types::InputMediaDocument> {
+        let document = self.raw()?;
+        Some(tl::types::InputMediaDocument {
+            id: tl::types::InputDocument {
+                id: document.id,
+                access_hash: document.access_hash,
+                file_reference: document.file_reference.clone(),
+            }
+            .into(),
             ttl_seconds: self.document.ttl_seconds,
             query: None,
-        }
+        })
     }
 
     pub fn id(&self) -> i64 {
@@ -217,38 +558,271 @@ impl Document {
         }
     }
 
-    /// Return the file's name.
+    /// Return the file's name, if any.
     ///
-    /// If the file was uploaded with no file name, the returned string will be empty.
-    pub fn name(&self) -> &str {
-        use tl::enums::Document as D;
+    /// The name is taken from the document's file name attribute, so it will be `None` if the
+    /// file was uploaded with no file name (or if the document is empty).
+    pub fn name(&self) -> Option<&str> {
+        self.raw()?.attributes.iter().find_map(|attr| match attr {
+            tl::enums::DocumentAttribute::Filename(attr) => Some(attr.file_name.as_ref()),
+            _ => None,
+        })
+    }
 
-        match self.document.document.as_ref().unwrap() {
-            D::Empty(_) => "",
-            D::Document(document) => document
-                .attributes
-                .iter()
-                .find_map(|attr| match attr {
-                    tl::enums::DocumentAttribute::Filename(attr) => Some(attr.file_name.as_ref()),
-                    _ => None,
-                })
-                .unwrap_or(""),
+    /// Download the document into the file at the given path.
+    ///
+    /// Returns [`DownloadError::NoLocation`] if the document is empty.
+    pub async fn download(&self, path: &Path) -> Result<(), DownloadError> {
+        self.download_with_progress(path, |_, _| {}).await
+    }
+
+    /// Download only the thumbnail of the document with the given type into the file at the
+    /// given path, which is much cheaper than downloading the entire document to show a preview.
+    ///
+    /// If `thumb_type` is empty, the largest thumbnail is downloaded. Returns
+    /// [`DownloadError::NoSize`] if the document has no thumbnails, or
+    /// [`DownloadError::UnknownThumbType`] if it has none of the given type. Either is reported
+    /// before making any request.
+    pub async fn download_thumb(&self, path: &Path, thumb_type: &str) -> Result<(), DownloadError> {
+        select_thumb(self.thumbs(), thumb_type)?
+            .download(path)
+            .await
+    }
+
+    /// Download the document into the file at the given path, reporting the progress.
+    ///
+    /// After every chunk is written, `progress` is called with the number of bytes downloaded so
+    /// far, and the size of the document (if known). The download waits for the callback to
+    /// return, so it should not block for long.
+    pub async fn download_with_progress<F: FnMut(u64, Option<u64>)>(
+        &self,
+        path: &Path,
+        progress: F,
+    ) -> Result<(), DownloadError> {
+        self.check_downloadable()?;
+        let mut file = fs::File::create(path).await?;
+        self.write_into(&mut file, &DownloadOptions::default(), progress)
+            .await?;
+        Ok(())
+    }
+
+    /// Download the document into the file at the given path, using the given options.
+    ///
+    /// The options are checked before the file is created, so invalid options leave no file
+    /// behind.
+    pub async fn download_with_options(
+        &self,
+        path: &Path,
+        options: &DownloadOptions,
+    ) -> Result<(), DownloadError> {
+        options.check()?;
+        self.check_downloadable()?;
+        let mut file = fs::File::create(path).await?;
+        self.write_into(&mut file, options, |_, _| {}).await?;
+        Ok(())
+    }
+
+    /// Download the document into the file at the given path, until it completes or the token
+    /// is cancelled.
+    ///
+    /// On cancellation, [`DownloadError::Cancelled`] is returned and the partial file is left
+    /// intact, so that [`Document::download_resume`] can continue the download later on.
+    pub async fn download_cancellable(
+        &self,
+        path: &Path,
+        token: &CancellationToken,
+    ) -> Result<(), DownloadError> {
+        let options = DownloadOptions {
+            cancellation: Some(token.clone()),
+            ..Default::default()
+        };
+        self.download_with_options(path, &options).await
+    }
+
+    /// Stream the contents of the document, chunk by chunk, as they are downloaded.
+    ///
+    /// Chunks are only fetched when the stream is polled, so the file can be forwarded
+    /// elsewhere (such as an HTTP response) without storing it first. The stream ends after the
+    /// last chunk, or yields a single error if the document can't be downloaded at all, such as
+    /// [`DownloadError::NoLocation`] if it is empty, or [`DownloadError::NotConnected`].
+    pub fn download_stream(&self) -> impl Stream<Item = Result<Bytes, DownloadError>> {
+        match attached(&self.client) {
+            Ok(client) => Either::Left(client.download_location_stream(self.to_input_location())),
+            Err(e) => Either::Right(stream::once(async { Err(e) })),
         }
     }
 
-    pub async fn download(&mut self, path: &Path) -> Result<(), io::Error> {
-        match &self.document.document {
-            Some(_) => {
-                if let Some(location) = self.to_input_location() {
-                    self.client.download_media_at_location(location, path).await
-                } else {
-                    Ok(())
-                }
+    /// Download the document into the given directory, returning the path of the new file.
+    ///
+    /// The file keeps its original name when it has one. Otherwise, the name is made from the
+    /// document's identifier, plus an extension guessed from its MIME type. If a file with the
+    /// same name already exists, a numeric suffix is added instead of overwriting it, such as
+    /// `photo (1).png`.
+    ///
+    /// File names that are not a single plain path component (for example, containing `..`)
+    /// are rejected, so the file can never be written outside of `dir`. Returns
+    /// [`DownloadError::NoLocation`] if the document is empty.
+    pub async fn download_to_dir(&self, dir: &Path) -> Result<PathBuf, DownloadError> {
+        let document = self.raw().ok_or(DownloadError::NoLocation)?;
+        let name = match self.name() {
+            Some(name) => name.to_string(),
+            None => match self.extension() {
+                Some(ext) => format!("{}.{}", document.id, ext),
+                None => document.id.to_string(),
+            },
+        };
+
+        if !is_plain_file_name(&name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("refusing to save file outside of the directory: {}", name),
+            )
+            .into());
+        }
+
+        self.check_downloadable()?;
+        download_to_new_file(self, dir, &name).await
+    }
+
+    /// Download the document into the file at the given path, returning the path written.
+    ///
+    /// If the path is an existing directory, the document is saved inside of it as done by
+    /// [`Document::download_to_dir`], including the numeric suffix to avoid overwriting files.
+    /// Otherwise, the path is returned as-is after downloading into it like
+    /// [`Document::download`].
+    pub async fn download_returning_path(
+        &self,
+        dir_or_path: &Path,
+    ) -> Result<PathBuf, DownloadError> {
+        if is_dir(dir_or_path).await {
+            self.download_to_dir(dir_or_path).await
+        } else {
+            self.download(dir_or_path).await?;
+            Ok(dir_or_path.to_path_buf())
+        }
+    }
+
+    /// Continue a previous download of the document into the file at the given path.
+    ///
+    /// Whatever was already downloaded is kept (except for a trailing partial chunk), and only
+    /// the rest of the document is fetched and appended. If the file doesn't exist, this is the
+    /// same as [`Document::download`]. If the file is larger than the document, it can't be
+    /// part of it, so it is downloaded again from scratch.
+    pub async fn download_resume(&self, path: &Path) -> Result<(), DownloadError> {
+        let location = self.to_input_location().ok_or(DownloadError::NoLocation)?;
+        let total = self.size().map(|size| size as u64);
+        attached(&self.client)?
+            .download_location_resume(location, path, total)
+            .await
+    }
+
+    /// Download `limit` bytes of the document into memory, starting at byte `offset`.
+    ///
+    /// This is useful to fetch only part of a large file, such as the portion of a video a
+    /// player is seeking to. Any range can be requested, and it is fetched with as many
+    /// requests as needed. Fewer bytes are returned if the document ends before the range does.
+    ///
+    /// Returns an I/O error of kind `InvalidInput` if `offset` or `limit` are negative.
+    pub async fn download_range(&self, offset: i64, limit: i64) -> Result<Vec<u8>, DownloadError> {
+        if offset < 0 || limit < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid range of {} bytes at offset {}", limit, offset),
+            )
+            .into());
+        }
+        let location = self.to_input_location().ok_or(DownloadError::NoLocation)?;
+        attached(&self.client)?
+            .download_location_range(location, offset as u64, limit as u64)
+            .await
+    }
+
+    /// Download the document into the file at the given path, unless it's already there.
+    ///
+    /// Returns `Ok(false)` without downloading anything if a file already exists at `path` with
+    /// the same length as the document, and `Ok(true)` after downloading it otherwise. If the
+    /// size of the document is unknown, there's no way to tell, so it is always downloaded.
+    pub async fn download_if_missing(&self, path: &Path) -> Result<bool, DownloadError> {
+        let existing = match fs::metadata(path).await {
+            Ok(metadata) => Some(metadata.len()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        if is_already_downloaded(existing, self.size()) {
+            return Ok(false);
+        }
+        self.download(path).await?;
+        Ok(true)
+    }
+
+    /// Download the document into the file at the given path with several requests at once.
+    ///
+    /// The file is split into `connections` contiguous ranges which are fetched concurrently and
+    /// written at their offset in a pre-allocated file. Because every request has to wait for a
+    /// round-trip to Telegram, having several in flight can make large files download several
+    /// times faster. The amount of ranges is capped at [`MAX_DOWNLOAD_CONNECTIONS`], and files
+    /// smaller than a chunk gain nothing from it.
+    ///
+    /// The ranges share a single connection. If the file is stored in a data center other than
+    /// the client's, requests to it are made one after the other, so it downloads no faster than
+    /// [`Document::download`].
+    /// Fails with [`DownloadError::SizeMismatch`] if any range comes back short.
+    ///
+    /// If the size of the document is unknown, it is downloaded serially as [`Document::download`]
+    /// would do.
+    ///
+    /// [`MAX_DOWNLOAD_CONNECTIONS`]: crate::client::files::MAX_DOWNLOAD_CONNECTIONS
+    pub async fn download_parallel(
+        &self,
+        path: &Path,
+        connections: usize,
+    ) -> Result<(), DownloadError> {
+        let location = self.to_input_location().ok_or(DownloadError::NoLocation)?;
+        match self.size() {
+            Some(size) => {
+                attached(&self.client)?
+                    .download_location_parallel(location, path, size as u64, connections)
+                    .await
             }
-            None => Ok(()),
+            None => self.download(path).await,
         }
     }
 
+    /// Stream the document into the given writer, returning how many bytes were written.
+    ///
+    /// The file is written chunk by chunk as it is fetched, so it never needs to be fully
+    /// buffered in memory. Returns [`DownloadError::NoLocation`] if the document is empty.
+    pub async fn download_into<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+    ) -> Result<u64, DownloadError> {
+        self.write_into(writer, &DownloadOptions::default(), |_, _| {})
+            .await
+    }
+
+    /// Fail if the document can't be downloaded, which is reported before creating any file.
+    fn check_downloadable(&self) -> Result<(), DownloadError> {
+        self.to_input_location().ok_or(DownloadError::NoLocation)?;
+        attached(&self.client).map(drop)
+    }
+
+    async fn write_into<W, F>(
+        &self,
+        writer: &mut W,
+        options: &DownloadOptions,
+        progress: F,
+    ) -> Result<u64, DownloadError>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(u64, Option<u64>),
+    {
+        let location = self.to_input_location().ok_or(DownloadError::NoLocation)?;
+        let total = self.size().map(|size| size as u64);
+        attached(&self.client)?
+            .download_location_into(location, writer, options, total, progress)
+            .await
+    }
+
     pub fn is_round_message(&self) -> bool {
         self.document
             .document
@@ -298,11 +872,19 @@ impl Document {
     }
 
     /// Get the file's MIME type, if any.
+    ///
+    /// This is the MIME type the file was sent with, which can be used to pick a file extension
+    /// or a `Content-Type`. Empty documents have no MIME type.
     pub fn mime_type(&self) -> Option<&str> {
-        match self.document.document.as_ref() {
-            Some(tl::enums::Document::Document(d)) => Some(d.mime_type.as_str()),
-            _ => None,
-        }
+        self.raw().map(|d| d.mime_type.as_str())
+    }
+
+    /// The extension to use when saving the file to disk, guessed from its MIME type.
+    ///
+    /// Only common MIME types are known, so this is `None` for the rest, as well as for empty
+    /// documents. The extension does not include the leading dot.
+    pub fn extension(&self) -> Option<&'static str> {
+        self.mime_type().and_then(mime_extension)
     }
 
     /// The date on which the file was created, if any.
@@ -316,111 +898,528 @@ impl Document {
         }
     }
 
-    /// The size of the file.
-    /// returns 0 if the document is empty.
-    pub fn size(&self) -> i32 {
-        match self.document.document.as_ref() {
-            Some(tl::enums::Document::Document(d)) => d.size,
-            _ => 0,
-        }
+    /// The identifier of the data center where the document is stored, or `None` if the
+    /// document is empty.
+    pub fn dc_id(&self) -> Option<i32> {
+        self.raw().map(|d| d.dc_id)
     }
-}
 
-impl Sticker {
-    pub(crate) fn from_document(document: &Document) -> Option<Self> {
-        match document.document.document {
-            Some(tl::enums::Document::Document(ref doc)) => {
-                let mut animated = false;
-                let mut sticker_attrs: Option<tl::types::DocumentAttributeSticker> = None;
-                for attr in &doc.attributes {
-                    match attr {
-                        tl::enums::DocumentAttribute::Sticker(s) => sticker_attrs = Some(s.clone()),
-                        tl::enums::DocumentAttribute::Animated => animated = true,
-                        _ => (),
-                    }
-                }
-                Some(Self {
-                    document: document.clone(),
-                    attrs: sticker_attrs?,
-                    animated,
-                })
-            }
-            _ => None,
-        }
+    /// For how many seconds the document can be seen after it's opened, if it self-destructs.
+    pub fn ttl_seconds(&self) -> Option<i32> {
+        self.document.ttl_seconds
     }
 
-    /// Get the emoji associated with the sticker.
-    pub fn emoji(&self) -> &str {
-        self.attrs.alt.as_str()
+    /// The size of the file, in bytes, as reported by the server.
+    ///
+    /// This does not perform any network request. Returns `None` if the document is empty.
+    pub fn size(&self) -> Option<i64> {
+        self.raw().map(|d| d.size as i64)
     }
 
-    /// Is this sticker an animated sticker?
-    pub fn is_animated(&self) -> bool {
-        self.animated
+    /// The duration of the video, in seconds, if this document is a video.
+    pub fn duration(&self) -> Option<i32> {
+        self.video_attribute().map(|attr| attr.duration)
     }
-}
 
-impl Contact {
-    pub(crate) fn from_media(contact: tl::types::MessageMediaContact) -> Self {
-        Self { contact }
+    /// The width and height of the video, if this document is a video.
+    pub fn video_dimensions(&self) -> Option<(i32, i32)> {
+        self.video_attribute().map(|attr| (attr.w, attr.h))
     }
 
-    pub(crate) fn to_input_media(&self) -> tl::types::InputMediaContact {
-        tl::types::InputMediaContact {
-            phone_number: self.contact.phone_number.clone(),
-            first_name: self.contact.first_name.clone(),
-            last_name: self.contact.last_name.clone(),
-            vcard: self.contact.vcard.clone(),
-        }
+    /// Whether this document is a round video message (also known as a "video note").
+    pub fn is_round_video(&self) -> bool {
+        self.video_attribute()
+            .map(|attr| attr.round_message)
+            .unwrap_or(false)
     }
 
-    /// The contact's phone number, in international format. This field will always be a non-empty
-    /// string of digits, although there's no guarantee that the number actually exists.
-    pub fn phone_number(&self) -> &str {
-        self.contact.phone_number.as_str()
+    /// The title of the song, if this document is an audio file and the title is known.
+    pub fn audio_title(&self) -> Option<&str> {
+        self.audio_attribute()?.title.as_deref()
     }
 
-    /// The contact's first name. Although official clients will always send a non-empty string,
-    /// it is possible for this field to be empty when sent via different means.
-    pub fn first_name(&self) -> &str {
-        self.contact.first_name.as_str()
+    /// The performer of the song, if this document is an audio file and the performer is known.
+    pub fn audio_performer(&self) -> Option<&str> {
+        self.audio_attribute()?.performer.as_deref()
     }
 
-    /// The contact's last name. May be empty if it's not set by sender.
-    pub fn last_name(&self) -> &str {
-        self.contact.last_name.as_str()
+    /// The duration of the audio, in seconds, if this document is an audio file.
+    pub fn audio_duration(&self) -> Option<i32> {
+        self.audio_attribute().map(|attr| attr.duration)
     }
 
-    /// Contact information in [vCard format][1]. Applications such as Telegram Desktop leave this
-    /// field empty. The vCard version used in this field could be any. The field may also contain
-    /// arbitrary text when sent by non-official clients.
-    ///
-    /// [1]: https://en.wikipedia.org/wiki/VCard
-    pub fn vcard(&self) -> &str {
-        self.contact.vcard.as_str()
+    /// Whether this document is a voice note, as opposed to a regular audio file.
+    pub fn is_voice(&self) -> bool {
+        self.audio_attribute()
+            .map(|attr| attr.voice)
+            .unwrap_or(false)
+    }
+
+    /// Whether this document is a sticker.
+    pub fn is_sticker(&self) -> bool {
+        self.sticker_attribute().is_some()
+    }
+
+    /// The emoji (or alternative text) associated with the sticker, if this document is one.
+    pub fn sticker_emoji(&self) -> Option<&str> {
+        self.sticker_attribute().map(|attr| attr.alt.as_str())
+    }
+
+    /// Whether this document is an animation, such as a GIF, which should be played
+    /// automatically and without sound.
+    pub fn is_animated(&self) -> bool {
+        self.raw()
+            .map(|d| is_animated(&d.attributes))
+            .unwrap_or(false)
+    }
+
+    /// The raw attributes of the document, including those this library has no accessor for.
+    /// Empty documents have no attributes.
+    pub fn attributes(&self) -> &[tl::enums::DocumentAttribute] {
+        self.raw().map(|d| d.attributes.as_slice()).unwrap_or(&[])
+    }
+
+    fn video_attribute(&self) -> Option<&tl::types::DocumentAttributeVideo> {
+        video_attribute(&self.raw()?.attributes)
+    }
+
+    fn audio_attribute(&self) -> Option<&tl::types::DocumentAttributeAudio> {
+        audio_attribute(&self.raw()?.attributes)
+    }
+
+    fn sticker_attribute(&self) -> Option<&tl::types::DocumentAttributeSticker> {
+        sticker_attribute(&self.raw()?.attributes)
+    }
+}
+
+/// Pick the largest of the thumbs which are not empty.
+fn largest_thumb(mut thumbs: Vec<PhotoSize>) -> Option<PhotoSize> {
+    let index = (0..thumbs.len())
+        .filter(|&i| !matches!(thumbs[i], PhotoSize::Empty(_)))
+        .max_by_key(|&i| thumbs[i].size())?;
+    Some(thumbs.swap_remove(index))
+}
+
+/// Pick the thumb of the given type, or the largest one if the type is empty.
+fn select_thumb(thumbs: Vec<PhotoSize>, thumb_type: &str) -> Result<PhotoSize, DownloadError> {
+    if thumb_type.is_empty() {
+        largest_thumb(thumbs).ok_or(DownloadError::NoSize)
+    } else if thumbs.is_empty() {
+        Err(DownloadError::NoSize)
+    } else {
+        thumbs
+            .into_iter()
+            .find(|t| t.photo_type() == thumb_type)
+            .ok_or_else(|| DownloadError::UnknownThumbType(thumb_type.to_string()))
+    }
+}
+
+fn mime_extension(mime_type: &str) -> Option<&'static str> {
+    Some(match mime_type {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "video/mp4" => "mp4",
+        "video/quicktime" => "mov",
+        "video/webm" => "webm",
+        "audio/mpeg" => "mp3",
+        "audio/ogg" => "ogg",
+        "audio/mp4" => "m4a",
+        "application/pdf" => "pdf",
+        "application/zip" => "zip",
+        "application/x-tgsticker" => "tgs",
+        "text/plain" => "txt",
+        _ => return None,
+    })
+}
+
+/// Whether a local file of the `existing` length (if any) is already the full document.
+fn is_already_downloaded(existing: Option<u64>, size: Option<i64>) -> bool {
+    match (existing, size) {
+        (Some(existing), Some(size)) => existing == size as u64,
+        _ => false,
+    }
+}
+
+/// Whether the name refers to a file directly inside a directory, and not anywhere else.
+fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+/// Whether the path exists and is a directory.
+async fn is_dir(path: &Path) -> bool {
+    fs::metadata(path)
+        .await
+        .map(|meta| meta.is_dir())
+        .unwrap_or(false)
+}
+
+/// Download into a new file with the given name inside of `dir`, adding a numeric suffix to the
+/// name for as long as a file with that name already exists.
+///
+/// The caller should check that the media can be downloaded first, or the file is created only
+/// to be removed again.
+async fn download_to_new_file(
+    media: &dyn Downloadable,
+    dir: &Path,
+    name: &str,
+) -> Result<PathBuf, DownloadError> {
+    let mut attempt = 0;
+    let path = loop {
+        let path = dir.join(numbered_file_name(name, attempt));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(_) => break path,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    match media.download(&path).await {
+        Ok(()) => Ok(path),
+        Err(e) => {
+            // Don't leave a partial file behind, or it would collide next time.
+            let _ = fs::remove_file(&path).await;
+            Err(e)
+        }
+    }
+}
+
+/// Add ` (n)` before the extension of the name, unless `n` is zero.
+fn numbered_file_name(name: &str, n: usize) -> String {
+    if n == 0 {
+        return name.to_string();
+    }
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => format!("{} ({}){}", &name[..dot], n, &name[dot..]),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+fn video_attribute(
+    attributes: &[tl::enums::DocumentAttribute],
+) -> Option<&tl::types::DocumentAttributeVideo> {
+    attributes.iter().find_map(|attr| match attr {
+        tl::enums::DocumentAttribute::Video(attr) => Some(attr),
+        _ => None,
+    })
+}
+
+fn audio_attribute(
+    attributes: &[tl::enums::DocumentAttribute],
+) -> Option<&tl::types::DocumentAttributeAudio> {
+    attributes.iter().find_map(|attr| match attr {
+        tl::enums::DocumentAttribute::Audio(attr) => Some(attr),
+        _ => None,
+    })
+}
+
+fn sticker_attribute(
+    attributes: &[tl::enums::DocumentAttribute],
+) -> Option<&tl::types::DocumentAttributeSticker> {
+    attributes.iter().find_map(|attr| match attr {
+        tl::enums::DocumentAttribute::Sticker(attr) => Some(attr),
+        _ => None,
+    })
+}
+
+fn is_animated(attributes: &[tl::enums::DocumentAttribute]) -> bool {
+    attributes
+        .iter()
+        .any(|attr| matches!(attr, tl::enums::DocumentAttribute::Animated))
+}
+
+impl Sticker {
+    pub(crate) fn from_document(document: &Document) -> Option<Self> {
+        match document.document.document {
+            Some(tl::enums::Document::Document(ref doc)) => {
+                let mut animated = false;
+                let mut sticker_attrs: Option<tl::types::DocumentAttributeSticker> = None;
+                for attr in &doc.attributes {
+                    match attr {
+                        tl::enums::DocumentAttribute::Sticker(s) => sticker_attrs = Some(s.clone()),
+                        tl::enums::DocumentAttribute::Animated => animated = true,
+                        _ => (),
+                    }
+                }
+                Some(Self {
+                    document: document.clone(),
+                    attrs: sticker_attrs?,
+                    animated,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the emoji associated with the sticker.
+    pub fn emoji(&self) -> &str {
+        self.attrs.alt.as_str()
+    }
+
+    /// Is this sticker an animated sticker?
+    pub fn is_animated(&self) -> bool {
+        self.animated
+    }
+}
+
+impl Contact {
+    pub(crate) fn from_media(
+        contact: tl::types::MessageMediaContact,
+        client: Option<Client>,
+    ) -> Self {
+        Self { contact, client }
+    }
+
+    pub(crate) fn to_input_media(&self) -> tl::types::InputMediaContact {
+        tl::types::InputMediaContact {
+            phone_number: self.contact.phone_number.clone(),
+            first_name: self.contact.first_name.clone(),
+            last_name: self.contact.last_name.clone(),
+            vcard: self.contact.vcard.clone(),
+        }
+    }
+
+    /// The contact's phone number, in international format. This field will always be a non-empty
+    /// string of digits, although there's no guarantee that the number actually exists.
+    pub fn phone_number(&self) -> &str {
+        self.contact.phone_number.as_str()
+    }
+
+    /// The contact's first name. Although official clients will always send a non-empty string,
+    /// it is possible for this field to be empty when sent via different means.
+    pub fn first_name(&self) -> &str {
+        self.contact.first_name.as_str()
+    }
+
+    /// The contact's last name. May be empty if it's not set by sender.
+    pub fn last_name(&self) -> &str {
+        self.contact.last_name.as_str()
+    }
+
+    /// Contact information in [vCard format][1]. Applications such as Telegram Desktop leave this
+    /// field empty. The vCard version used in this field could be any. The field may also contain
+    /// arbitrary text when sent by non-official clients.
+    ///
+    /// [1]: https://en.wikipedia.org/wiki/VCard
+    pub fn vcard(&self) -> &str {
+        self.contact.vcard.as_str()
+    }
+
+    /// The identifier of the Telegram user with this phone number, if the contact has an account
+    /// that the sender knows about.
+    pub fn user_id(&self) -> Option<i32> {
+        match self.contact.user_id {
+            0 => None,
+            id => Some(id),
+        }
+    }
+
+    /// Import this contact into the address book of the logged-in account, using its phone
+    /// number, first and last name.
+    ///
+    /// Contacts shared by non-official clients may lack a phone number, in which case there is
+    /// nothing to import and [`ImportContactError::NoPhoneNumber`] is returned without making
+    /// any request.
+    pub async fn import(&self) -> Result<(), ImportContactError> {
+        if self.contact.phone_number.is_empty() {
+            return Err(ImportContactError::NoPhoneNumber);
+        }
+        let client = self.client.as_ref().ok_or(ImportContactError::Detached)?;
+        client
+            .invoke(&tl::functions::contacts::ImportContacts {
+                contacts: vec![self.to_input_contact().into()],
+            })
+            .await?;
+        Ok(())
+    }
+
+    fn to_input_contact(&self) -> tl::types::InputPhoneContact {
+        tl::types::InputPhoneContact {
+            client_id: 0,
+            phone: self.contact.phone_number.clone(),
+            first_name: self.contact.first_name.clone(),
+            last_name: self.contact.last_name.clone(),
+        }
+    }
+}
+
+/// The error type which is returned when importing a [`Contact`] fails.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ImportContactError {
+    /// The contact has no phone number to import.
+    NoPhoneNumber,
+    /// The contact has no client attached, see [`Media::with_client`].
+    Detached,
+    /// The request to import the contact failed.
+    Other(InvocationError),
+}
+
+impl fmt::Display for ImportContactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ImportContactError::*;
+        match self {
+            NoPhoneNumber => write!(f, "import contact error: contact has no phone number"),
+            Detached => write!(f, "import contact error: no client attached"),
+            Other(e) => write!(f, "import contact error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportContactError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImportContactError::Other(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<InvocationError> for ImportContactError {
+    fn from(error: InvocationError) -> Self {
+        Self::Other(error)
     }
 }
 
 impl Uploaded {
     pub(crate) fn from_raw(input_file: tl::enums::InputFile) -> Self {
-        Self { input_file }
+        Self {
+            input_file,
+            size: None,
+        }
+    }
+
+    pub(crate) fn with_size(mut self, size: usize) -> Self {
+        self.size = Some(size as i64);
+        self
+    }
+
+    fn parts(&self) -> i32 {
+        match &self.input_file {
+            tl::enums::InputFile::File(f) => f.parts,
+            tl::enums::InputFile::Big(f) => f.parts,
+        }
+    }
+
+    fn identity(&self) -> i64 {
+        match &self.input_file {
+            tl::enums::InputFile::File(f) => f.id,
+            tl::enums::InputFile::Big(f) => f.id,
+        }
     }
 
-    pub(crate) fn name(&self) -> &str {
+    /// The name the file was uploaded with.
+    pub fn name(&self) -> &str {
         match &self.input_file {
             tl::enums::InputFile::File(f) => f.name.as_ref(),
             tl::enums::InputFile::Big(f) => f.name.as_ref(),
         }
     }
+
+    /// The MD5 checksum of the uploaded file, as a lowercase hexadecimal string.
+    ///
+    /// Telegram verifies this checksum when the file is used, rejecting corrupted uploads. It
+    /// is only computed for small files, so this is `None` for big files, which are uploaded in
+    /// parallel parts and have no checksum.
+    pub fn md5_checksum(&self) -> Option<&str> {
+        match &self.input_file {
+            tl::enums::InputFile::File(f) => Some(f.md5_checksum.as_ref()),
+            tl::enums::InputFile::Big(_) => None,
+        }
+    }
+
+    /// Turn the uploaded file into media which is sent as a photo, for requests that take raw
+    /// media such as [`Client::edit_message_media`].
+    ///
+    /// To send the file in a new message, use [`InputMessage::photo`] instead.
+    ///
+    /// [`InputMessage::photo`]: crate::InputMessage::photo
+    pub fn into_input_media_photo(self) -> tl::enums::InputMedia {
+        tl::types::InputMediaUploadedPhoto {
+            file: self.input_file,
+            stickers: None,
+            ttl_seconds: None,
+        }
+        .into()
+    }
+
+    /// Turn the uploaded file into media which is sent as a document with the given MIME type
+    /// and attributes, for requests that take raw media such as [`Client::edit_message_media`].
+    ///
+    /// The file name the file was uploaded with is kept, unless one of the attributes is a
+    /// [`Attribute::FileName`]. To send the file in a new message, use
+    /// [`InputMessage::document`] instead.
+    ///
+    /// [`InputMessage::document`]: crate::InputMessage::document
+    pub fn into_input_media_document(
+        self,
+        mime_type: &str,
+        attributes: Vec<Attribute>,
+    ) -> tl::enums::InputMedia {
+        let mut attributes = attributes
+            .into_iter()
+            .map(tl::enums::DocumentAttribute::from)
+            .collect::<Vec<_>>();
+        if !attributes
+            .iter()
+            .any(|attr| matches!(attr, tl::enums::DocumentAttribute::Filename(_)))
+        {
+            attributes.push(
+                tl::types::DocumentAttributeFilename {
+                    file_name: self.name().to_string(),
+                }
+                .into(),
+            );
+        }
+
+        tl::types::InputMediaUploadedDocument {
+            nosound_video: false,
+            force_file: false,
+            file: self.input_file,
+            thumb: None,
+            mime_type: mime_type.to_string(),
+            attributes,
+            stickers: None,
+            ttl_seconds: None,
+        }
+        .into()
+    }
+
+    /// How many bytes were uploaded, if known.
+    ///
+    /// Telegram only keeps track of the amount of parts, so this is recorded while uploading.
+    pub fn size(&self) -> Option<i64> {
+        self.size
+    }
 }
 
-impl Geo {
-    pub(crate) fn from_media(geo: tl::types::MessageMediaGeo, client: Client) -> Self {
-        Self { geo, client }
+impl GeoPoint {
+    /// The fields of the point with the coordinates as bits, which (unlike `f64`) can be
+    /// compared and hashed consistently.
+    fn raw_key(point: &tl::enums::GeoPoint) -> Option<(u64, u64, i64, Option<i32>)> {
+        match point {
+            tl::enums::GeoPoint::Point(point) => Some((
+                point.lat.to_bits(),
+                point.long.to_bits(),
+                point.access_hash,
+                point.accuracy_radius,
+            )),
+            tl::enums::GeoPoint::Empty => None,
+        }
     }
 
-    pub fn point(&self) -> Option<GeoPoint> {
-        match &self.geo.geo {
+    fn from_raw(point: &tl::enums::GeoPoint) -> Option<Self> {
+        match point {
             tl::enums::GeoPoint::Point(point) => Some(GeoPoint {
                 latitude: point.lat,
                 longitude: point.long,
@@ -429,85 +1428,831 @@ impl Geo {
             tl::enums::GeoPoint::Empty => None,
         }
     }
+
+    /// The great-circle distance, in meters, from this point to the point at the given latitude
+    /// and longitude (in degrees), as computed by the haversine formula.
+    ///
+    /// The Earth is treated as a sphere, so the result may be off by up to around 0.5%.
+    pub fn distance_to(&self, latitude: f64, longitude: f64) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_long = (longitude - self.longitude).to_radians();
+
+        let a =
+            (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_long / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+
+    fn to_input_geo_point(point: Option<&GeoPoint>) -> tl::enums::InputGeoPoint {
+        match point {
+            Some(point) => tl::types::InputGeoPoint {
+                lat: point.latitude,
+                long: point.longitude,
+                accuracy_radius: point.accuracy_radius,
+            }
+            .into(),
+            None => tl::enums::InputGeoPoint::Empty,
+        }
+    }
+}
+
+impl Geo {
+    fn from_media(geo: tl::types::MessageMediaGeo, client: Option<Client>) -> Self {
+        Self { geo, client }
+    }
+
+    /// Where the location is, or `None` if the location is unknown.
+    pub fn point(&self) -> Option<GeoPoint> {
+        GeoPoint::from_raw(&self.geo.geo)
+    }
+
+    pub(crate) fn to_input_media(&self) -> tl::types::InputMediaGeoPoint {
+        tl::types::InputMediaGeoPoint {
+            geo_point: GeoPoint::to_input_geo_point(self.point().as_ref()),
+        }
+    }
+
+    /// The latitude of the location, in degrees, or `None` if the location is unknown.
+    pub fn latitude(&self) -> Option<f64> {
+        self.point().map(|point| point.latitude)
+    }
+
+    /// The longitude of the location, in degrees, or `None` if the location is unknown.
+    pub fn longitude(&self) -> Option<f64> {
+        self.point().map(|point| point.longitude)
+    }
+
+    /// The estimated horizontal accuracy of the location, in meters, if the sender provided it.
+    pub fn accuracy_radius(&self) -> Option<i32> {
+        self.point()?.accuracy_radius
+    }
+
+    /// The distance, in meters, from this location to the given latitude and longitude, or
+    /// `None` if the location is unknown.
+    ///
+    /// See [`GeoPoint::distance_to`] for how the distance is computed.
+    pub fn distance_to(&self, latitude: f64, longitude: f64) -> Option<f64> {
+        Some(self.point()?.distance_to(latitude, longitude))
+    }
 }
 
 impl GeoLive {
-    pub(crate) fn from_media(geo: tl::types::MessageMediaGeoLive, client: Client) -> Self {
+    fn from_media(geo: tl::types::MessageMediaGeoLive, client: Option<Client>) -> Self {
         Self { geo, client }
     }
 
+    /// The last known position of the sender, or `None` if the location is unknown (for example,
+    /// once the live location has been stopped).
     pub fn point(&self) -> Option<GeoPoint> {
-        match &self.geo.geo {
-            tl::enums::GeoPoint::Point(point) => Some(GeoPoint {
-                latitude: point.lat,
-                longitude: point.long,
-                accuracy_radius: point.accuracy_radius,
-            }),
-            tl::enums::GeoPoint::Empty => None,
+        GeoPoint::from_raw(&self.geo.geo)
+    }
+
+    pub(crate) fn to_input_media(&self) -> tl::types::InputMediaGeoLive {
+        tl::types::InputMediaGeoLive {
+            stopped: false,
+            geo_point: GeoPoint::to_input_geo_point(self.point().as_ref()),
+            heading: self.geo.heading,
+            period: Some(self.geo.period),
+            proximity_notification_radius: self.geo.proximity_notification_radius,
         }
     }
 
+    /// The direction the sender is moving towards, in degrees (from 1 to 360), if known.
     pub fn heading(&self) -> Option<i32> {
         self.geo.heading
     }
 
+    /// The maximum distance, in meters, at which a notification should be sent to other chat
+    /// members when the sender approaches them, if enabled.
     pub fn proximity_notification_radius(&self) -> Option<i32> {
         self.geo.proximity_notification_radius
     }
 
+    /// For how long the location is shared, in seconds, since the message was sent.
     pub fn period(&self) -> i32 {
         self.geo.period
     }
 }
 
-impl Media {
-    pub(crate) fn from_raw(media: tl::enums::MessageMedia, client: Client) -> Option<Self> {
-        use tl::enums::MessageMedia as M;
-
-        // TODO implement the rest
-        match media {
-            M::Empty => None,
-            M::Photo(photo) => Some(Self::Photo(Photo::from_media(photo, client))),
-            M::Geo(geo) => Some(Self::Geo(Geo::from_media(geo, client))),
-            M::Contact(contact) => Some(Self::Contact(Contact::from_media(contact))),
-            M::Unsupported => None,
-            M::Document(document) => {
-                let document = Document::from_media(document, client);
-                Some(if let Some(sticker) = Sticker::from_document(&document) {
-                    Self::Sticker(sticker)
-                } else {
-                    Self::Document(document)
-                })
-            }
-            M::WebPage(_) => None,
-            M::Venue(_) => None,
-            M::Game(_) => None,
-            M::Invoice(_) => None,
-            M::GeoLive(geo) => Some(Self::GeoLive(GeoLive::from_media(geo, client))),
-            M::Poll(_) => None,
-            M::Dice(_) => None,
+impl WebPage {
+    fn from_media(webpage: tl::types::MessageMediaWebPage, client: Option<Client>) -> Self {
+        Self {
+            webpage: Box::new(webpage),
+            client,
         }
     }
 
-    pub(crate) fn to_input_media(&self) -> tl::enums::InputMedia {
-        match self {
-            Media::Photo(photo) => photo.to_input_media().into(),
-            Media::Document(document) => document.to_input_media().into(),
-            Media::Sticker(sticker) => sticker.document.to_input_media().into(),
-            Media::Contact(contact) => contact.to_input_media().into(),
-            Media::Geo(_) => panic!("Unexpected"),
-            Media::GeoLive(_) => panic!("Unexpected"),
+    /// The web page preview, if Telegram has finished generating it and the page could be
+    /// previewed at all.
+    fn raw(&self) -> Option<&tl::types::WebPage> {
+        match &self.webpage.webpage {
+            tl::enums::WebPage::Page(page) => Some(page),
+            tl::enums::WebPage::Empty(_)
+            | tl::enums::WebPage::Pending(_)
+            | tl::enums::WebPage::NotModified(_) => None,
         }
     }
 
-    pub(crate) fn to_input_location(&self) -> Option<tl::enums::InputFileLocation> {
-        match self {
-            Media::Photo(photo) => photo.to_input_location(),
-            Media::Document(document) => document.to_input_location(),
-            Media::Sticker(sticker) => sticker.document.to_input_location(),
-            Media::Contact(_) => None,
+    /// Whether Telegram is still generating the preview for this page.
+    pub fn is_pending(&self) -> bool {
+        matches!(self.webpage.webpage, tl::enums::WebPage::Pending(_))
+    }
+
+    /// The full URL of the page.
+    pub fn url(&self) -> Option<&str> {
+        self.raw().map(|page| page.url.as_str())
+    }
+
+    /// The URL of the page as it should be displayed to the user, usually without the scheme.
+    pub fn display_url(&self) -> Option<&str> {
+        self.raw().map(|page| page.display_url.as_str())
+    }
+
+    /// The title of the page, if any.
+    pub fn title(&self) -> Option<&str> {
+        self.raw()?.title.as_deref()
+    }
+
+    /// A short description of the page's content, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.raw()?.description.as_deref()
+    }
+
+    /// The name of the site the page belongs to, if any.
+    pub fn site_name(&self) -> Option<&str> {
+        self.raw()?.site_name.as_deref()
+    }
+
+    /// The photo shown in the preview, if any.
+    ///
+    /// The photo shares the client of the web page, so it can be downloaded right away.
+    pub fn photo(&self) -> Option<Photo> {
+        self.raw()?
+            .photo
+            .clone()
+            .map(|photo| Photo::from_photo(photo, self.client.clone()))
+    }
+
+    /// The document embedded in the preview, such as a video or a GIF, if any.
+    ///
+    /// The document shares the client of the web page, so it can be downloaded right away.
+    pub fn document(&self) -> Option<Document> {
+        self.raw()?.document.clone().map(|document| {
+            Document::from_media(
+                tl::types::MessageMediaDocument {
+                    document: Some(document),
+                    ttl_seconds: None,
+                },
+                self.client.clone(),
+            )
+        })
+    }
+}
+
+impl Poll {
+    fn from_media(poll: tl::types::MessageMediaPoll, client: Option<Client>) -> Self {
+        Self {
+            poll,
+            client,
+            message: None,
+        }
+    }
+
+    fn raw(&self) -> &tl::types::Poll {
+        match &self.poll.poll {
+            tl::enums::Poll::Poll(poll) => poll,
+        }
+    }
+
+    /// The question being asked.
+    pub fn question(&self) -> &str {
+        self.raw().question.as_str()
+    }
+
+    /// The answers the poll can be voted with, in the order they should be displayed.
+    pub fn options(&self) -> Vec<PollAnswer> {
+        self.raw()
+            .answers
+            .iter()
+            .map(|answer| match answer {
+                tl::enums::PollAnswer::Answer(answer) => PollAnswer {
+                    text: answer.text.clone(),
+                    option: answer.option.clone(),
+                },
+            })
+            .collect()
+    }
+
+    /// Whether the poll is closed and no longer accepts votes.
+    pub fn is_closed(&self) -> bool {
+        self.raw().closed
+    }
+
+    /// Whether the poll is a quiz, with a single correct answer.
+    pub fn is_quiz(&self) -> bool {
+        self.raw().quiz
+    }
+
+    /// The results of the poll, as known at the time the message was received.
+    pub fn results(&self) -> PollResults {
+        PollResults::from_raw(&self.poll.results)
+    }
+
+    /// Vote for the given answers, identified by their [`PollAnswer::option`].
+    ///
+    /// Several answers can only be chosen if the poll allows multiple choices, and quizzes
+    /// can't be voted again. Telegram responds with an error otherwise. Returns the updated
+    /// results if Telegram sent them back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(message: grammers_client::types::Message) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::Media;
+    ///
+    /// if let Some(Media::Poll(poll)) = message.media() {
+    ///     let first = poll.options().remove(0);
+    ///     poll.vote(&[&first.option]).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Fails with [`PollError::NoOptions`] if `options` is empty, and with
+    /// [`PollError::Detached`] if the poll did not come from a [`Message::media`] (for example,
+    /// because it was deserialized).
+    ///
+    /// [`Message::media`]: crate::types::Message::media
+    pub async fn vote(&self, options: &[&[u8]]) -> Result<Option<PollResults>, PollError> {
+        if options.is_empty() {
+            return Err(PollError::NoOptions);
+        }
+        self.send_vote(options.iter().map(|option| option.to_vec()).collect())
+            .await
+    }
+
+    /// Retract the votes of the current user, so that they can vote again.
+    ///
+    /// Quizzes can't be voted again, so votes can't be retracted from them. Returns the
+    /// updated results if Telegram sent them back.
+    ///
+    /// Fails with [`PollError::Detached`] if the poll did not come from a [`Message::media`].
+    ///
+    /// [`Message::media`]: crate::types::Message::media
+    pub async fn retract_vote(&self) -> Result<Option<PollResults>, PollError> {
+        self.send_vote(Vec::new()).await
+    }
+
+    /// Close the poll, so that no more votes are accepted.
+    ///
+    /// Only the creator of the poll can close it, by editing the message containing it.
+    /// Closing a poll can't be undone.
+    ///
+    /// Fails with [`PollError::Detached`] if the poll did not come from a [`Message::media`].
+    ///
+    /// [`Message::media`]: crate::types::Message::media
+    pub async fn close(&self) -> Result<(), PollError> {
+        let (client, (peer, msg_id)) = self.context()?;
+        client
+            .invoke(&tl::functions::messages::EditMessage {
+                no_webpage: false,
+                peer: peer.clone(),
+                id: *msg_id,
+                message: None,
+                media: Some(self.to_closed_input_media().into()),
+                reply_markup: None,
+                entities: None,
+                schedule_date: None,
+            })
+            .await?;
+        Ok(())
+    }
+
+    fn to_closed_input_media(&self) -> tl::types::InputMediaPoll {
+        let mut poll = self.raw().clone();
+        poll.closed = true;
+        // Quizzes must keep saying which answers are correct, which only the creator knows.
+        let correct_answers = if poll.quiz {
+            Some(
+                self.results()
+                    .answers
+                    .into_iter()
+                    .filter(|answer| answer.correct)
+                    .map(|answer| answer.option)
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        tl::types::InputMediaPoll {
+            poll: poll.into(),
+            correct_answers,
+            solution: None,
+            solution_entities: None,
+        }
+    }
+
+    /// The client and message needed to act on the poll, or [`PollError::Detached`].
+    fn context(&self) -> Result<(&Client, &(tl::enums::InputPeer, i32)), PollError> {
+        match (&self.client, &self.message) {
+            (Some(client), Some(message)) => Ok((client, message)),
+            _ => Err(PollError::Detached),
+        }
+    }
+
+    async fn send_vote(&self, options: Vec<Vec<u8>>) -> Result<Option<PollResults>, PollError> {
+        let (client, (peer, msg_id)) = self.context()?;
+        let updates = client
+            .invoke(&tl::functions::messages::SendVote {
+                peer: peer.clone(),
+                msg_id: *msg_id,
+                options,
+            })
+            .await?;
+        Ok(PollResults::from_updates(&updates, self.raw().id))
+    }
+}
+
+/// The error type which is returned when voting in or closing a [`Poll`] fails.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PollError {
+    /// No answers were chosen to vote with.
+    NoOptions,
+    /// The poll does not belong to a message received by a client, see [`Message::media`].
+    ///
+    /// [`Message::media`]: crate::types::Message::media
+    Detached,
+    /// The request to Telegram failed.
+    Other(InvocationError),
+}
+
+impl fmt::Display for PollError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PollError::*;
+        match self {
+            NoOptions => write!(f, "poll error: at least one option must be chosen"),
+            Detached => write!(f, "poll error: poll does not belong to a received message"),
+            Other(e) => write!(f, "poll error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PollError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PollError::Other(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<InvocationError> for PollError {
+    fn from(error: InvocationError) -> Self {
+        Self::Other(error)
+    }
+}
+
+impl PollResults {
+    fn from_raw(results: &tl::enums::PollResults) -> Self {
+        let tl::enums::PollResults::Results(results) = results;
+        PollResults {
+            answers: results
+                .results
+                .iter()
+                .flatten()
+                .map(|voters| match voters {
+                    tl::enums::PollAnswerVoters::Voters(voters) => PollAnswerVoters {
+                        option: voters.option.clone(),
+                        voters: voters.voters,
+                        chosen: voters.chosen,
+                        correct: voters.correct,
+                    },
+                })
+                .collect(),
+            total_voters: results.total_voters,
+        }
+    }
+
+    /// Find the results of the given poll within the updates Telegram responded with.
+    fn from_updates(updates: &tl::enums::Updates, poll_id: i64) -> Option<Self> {
+        let updates = match updates {
+            tl::enums::Updates::Updates(updates) => &updates.updates,
+            tl::enums::Updates::Combined(updates) => &updates.updates,
+            _ => return None,
+        };
+        updates.iter().find_map(|update| match update {
+            tl::enums::Update::MessagePoll(update) if update.poll_id == poll_id => {
+                Some(PollResults::from_raw(&update.results))
+            }
+            _ => None,
+        })
+    }
+}
+
+impl Dice {
+    pub(crate) fn from_media(dice: tl::types::MessageMediaDice) -> Self {
+        Self { dice }
+    }
+
+    pub(crate) fn to_input_media(&self) -> tl::types::InputMediaDice {
+        tl::types::InputMediaDice {
+            emoticon: self.dice.emoticon.clone(),
+        }
+    }
+
+    /// The emoji the dice was thrown with, which determines its animation (such as 🎲 or 🎯).
+    pub fn emoticon(&self) -> &str {
+        self.dice.emoticon.as_str()
+    }
+
+    /// The value the dice landed on. The range depends on the emoji used.
+    pub fn value(&self) -> i32 {
+        self.dice.value
+    }
+}
+
+impl Venue {
+    pub(crate) fn from_media(venue: tl::types::MessageMediaVenue) -> Self {
+        Self { venue }
+    }
+
+    pub(crate) fn to_input_media(&self) -> tl::types::InputMediaVenue {
+        tl::types::InputMediaVenue {
+            geo_point: GeoPoint::to_input_geo_point(self.geo().as_ref()),
+            title: self.venue.title.clone(),
+            address: self.venue.address.clone(),
+            provider: self.venue.provider.clone(),
+            venue_id: self.venue.venue_id.clone(),
+            venue_type: self.venue.venue_type.clone(),
+        }
+    }
+
+    /// The name of the venue.
+    pub fn title(&self) -> &str {
+        self.venue.title.as_str()
+    }
+
+    /// The address of the venue.
+    pub fn address(&self) -> &str {
+        self.venue.address.as_str()
+    }
+
+    /// The venue provider, such as `foursquare`. May be empty.
+    pub fn provider(&self) -> &str {
+        self.venue.provider.as_str()
+    }
+
+    /// The identifier of the venue in the provider's database. May be empty.
+    pub fn venue_id(&self) -> &str {
+        self.venue.venue_id.as_str()
+    }
+
+    /// The type of the venue in the provider's database. May be empty.
+    pub fn venue_type(&self) -> &str {
+        self.venue.venue_type.as_str()
+    }
+
+    /// Where the venue is located, if known.
+    pub fn geo(&self) -> Option<GeoPoint> {
+        GeoPoint::from_raw(&self.venue.geo)
+    }
+}
+
+impl Invoice {
+    pub(crate) fn from_media(invoice: tl::types::MessageMediaInvoice) -> Self {
+        Self { invoice }
+    }
+
+    /// The name of the product being sold.
+    pub fn title(&self) -> &str {
+        self.invoice.title.as_str()
+    }
+
+    /// The description of the product being sold.
+    pub fn description(&self) -> &str {
+        self.invoice.description.as_str()
+    }
+
+    /// The three-letter ISO 4217 code of the currency used.
+    pub fn currency(&self) -> &str {
+        self.invoice.currency.as_str()
+    }
+
+    /// The total price, in the smallest units of the currency (for example, cents for `USD`).
+    pub fn total_amount(&self) -> i64 {
+        self.invoice.total_amount
+    }
+
+    /// Whether this is a test invoice, which can't be used to pay with real money.
+    pub fn is_test(&self) -> bool {
+        self.invoice.test
+    }
+
+    /// The parameter used to generate the invoice through a deep link to the bot.
+    pub fn start_param(&self) -> &str {
+        self.invoice.start_param.as_str()
+    }
+
+    /// The product's photo, if any.
+    ///
+    /// Unlike other media, this photo is hosted outside of Telegram, so it is returned as the
+    /// raw web document instead of a [`Photo`].
+    pub fn photo(&self) -> Option<&tl::enums::WebDocument> {
+        self.invoice.photo.as_ref()
+    }
+}
+
+impl Game {
+    fn from_media(game: tl::types::MessageMediaGame, client: Option<Client>) -> Self {
+        Self { game, client }
+    }
+
+    fn raw(&self) -> &tl::types::Game {
+        match &self.game.game {
+            tl::enums::Game::Game(game) => game,
+        }
+    }
+
+    pub(crate) fn to_input_media(&self) -> tl::types::InputMediaGame {
+        tl::types::InputMediaGame {
+            id: tl::types::InputGameId {
+                id: self.raw().id,
+                access_hash: self.raw().access_hash,
+            }
+            .into(),
+        }
+    }
+
+    /// The title of the game.
+    pub fn title(&self) -> &str {
+        self.raw().title.as_str()
+    }
+
+    /// The description of the game.
+    pub fn description(&self) -> &str {
+        self.raw().description.as_str()
+    }
+
+    /// The unique name the bot uses to refer to this game.
+    pub fn short_name(&self) -> &str {
+        self.raw().short_name.as_str()
+    }
+
+    /// The photo shown as the game's cover, unless it's empty.
+    pub fn photo(&self) -> Option<Photo> {
+        match &self.raw().photo {
+            tl::enums::Photo::Empty(_) => None,
+            photo => Some(Photo::from_photo(photo.clone(), self.client.clone())),
+        }
+    }
+
+    /// The animation shown as the game's cover, if any.
+    pub fn document(&self) -> Option<Document> {
+        self.raw().document.clone().map(|document| {
+            Document::from_media(
+                tl::types::MessageMediaDocument {
+                    document: Some(document),
+                    ttl_seconds: None,
+                },
+                self.client.clone(),
+            )
+        })
+    }
+}
+
+impl Media {
+    pub(crate) fn from_raw(media: tl::enums::MessageMedia, client: Client) -> Option<Self> {
+        Self::from_raw_with(media, Some(client))
+    }
+
+    fn from_raw_with(media: tl::enums::MessageMedia, client: Option<Client>) -> Option<Self> {
+        use tl::enums::MessageMedia as M;
+
+        match media {
+            M::Empty => None,
+            M::Photo(photo) => Some(Self::Photo(Photo::from_media(photo, client))),
+            M::Geo(geo) => Some(Self::Geo(Geo::from_media(geo, client))),
+            M::Contact(contact) => Some(Self::Contact(Contact::from_media(contact, client))),
+            M::Unsupported => Some(Self::Unsupported),
+            M::Document(document) => {
+                let document = Document::from_media(document, client);
+                Some(if let Some(sticker) = Sticker::from_document(&document) {
+                    Self::Sticker(sticker)
+                } else {
+                    Self::Document(document)
+                })
+            }
+            M::WebPage(webpage) => Some(Self::WebPage(WebPage::from_media(webpage, client))),
+            M::Venue(venue) => Some(Self::Venue(Venue::from_media(venue))),
+            M::Game(game) => Some(Self::Game(Game::from_media(game, client))),
+            M::Invoice(invoice) => Some(Self::Invoice(Invoice::from_media(invoice))),
+            M::GeoLive(geo) => Some(Self::GeoLive(GeoLive::from_media(geo, client))),
+            M::Poll(poll) => Some(Self::Poll(Poll::from_media(poll, client))),
+            M::Dice(dice) => Some(Self::Dice(Dice::from_media(dice))),
+        }
+    }
+
+    /// Return the photo, if this media is a photo.
+    pub fn photo(&self) -> Option<&Photo> {
+        match self {
+            Media::Photo(photo) => Some(photo),
+            _ => None,
+        }
+    }
+
+    /// Return the document, if this media is a document.
+    ///
+    /// Stickers are also documents, but they have their own [`Media::Sticker`] variant, so this
+    /// method returns `None` for them.
+    pub fn document(&self) -> Option<&Document> {
+        match self {
+            Media::Document(document) => Some(document),
+            _ => None,
+        }
+    }
+
+    /// The name of the file in this media, if any, which can be used to name it on disk.
+    ///
+    /// Only documents (including stickers) can have a name, as returned by [`Document::name`].
+    /// Photos and all other media have none. Files which were just uploaded have their own
+    /// [`Uploaded::name`].
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Media::Document(document) => document.name(),
+            Media::Sticker(sticker) => sticker.document.name(),
+            _ => None,
+        }
+    }
+
+    /// The size of the file in this media, in bytes, if any, as reported by the server.
+    ///
+    /// For documents (including stickers) this is [`Document::size`], and for photos it is the
+    /// size of their largest size, which is what [`Photo::download`] fetches. All other media,
+    /// such as locations, polls or dice, have no size. Files which were just uploaded have
+    /// their own [`Uploaded::size`].
+    pub fn size(&self) -> Option<i64> {
+        match self {
+            Media::Photo(photo) => photo.largest_size().map(|size| size.size() as i64),
+            Media::Document(document) => document.size(),
+            Media::Sticker(sticker) => sticker.document.size(),
+            _ => None,
+        }
+    }
+
+    /// The kind of this media, to tell media apart without matching on (or cloning) its data.
+    pub fn kind(&self) -> MediaKind {
+        match self {
+            Media::Photo(_) => MediaKind::Photo,
+            Media::Document(_) => MediaKind::Document,
+            Media::Sticker(_) => MediaKind::Sticker,
+            Media::Contact(_) => MediaKind::Contact,
+            Media::Geo(_) => MediaKind::Geo,
+            Media::GeoLive(_) => MediaKind::GeoLive,
+            Media::WebPage(_) => MediaKind::WebPage,
+            Media::Poll(_) => MediaKind::Poll,
+            Media::Dice(_) => MediaKind::Dice,
+            Media::Venue(_) => MediaKind::Venue,
+            Media::Invoice(_) => MediaKind::Invoice,
+            Media::Game(_) => MediaKind::Game,
+            Media::Unsupported => MediaKind::Unsupported,
+        }
+    }
+
+    /// Whether this media can be understood by this version of the library.
+    ///
+    /// It's `false` only for [`Media::Unsupported`].
+    pub fn is_supported(&self) -> bool {
+        !matches!(self, Media::Unsupported)
+    }
+
+    /// Whether this media is a photo.
+    pub fn is_photo(&self) -> bool {
+        self.photo().is_some()
+    }
+
+    /// Whether this media is a document.
+    pub fn is_document(&self) -> bool {
+        self.document().is_some()
+    }
+
+    /// Build the input media needed to send this media again, without re-uploading it.
+    ///
+    /// Returns `None` for media that Telegram doesn't let clients send as-is, such as link
+    /// previews, polls or invoices, and for photos and documents that are empty.
+    pub fn to_input_media(&self) -> Option<tl::enums::InputMedia> {
+        Some(match self {
+            Media::Photo(photo) => photo.to_input_media()?.into(),
+            Media::Document(document) => document.to_input_media()?.into(),
+            Media::Sticker(sticker) => sticker.document.to_input_media()?.into(),
+            Media::Contact(contact) => contact.to_input_media().into(),
+            Media::Geo(geo) => geo.to_input_media().into(),
+            Media::GeoLive(geo) => geo.to_input_media().into(),
+            Media::WebPage(_) => return None,
+            Media::Poll(_) => return None,
+            Media::Dice(dice) => dice.to_input_media().into(),
+            Media::Venue(venue) => venue.to_input_media().into(),
+            Media::Invoice(_) => return None,
+            Media::Game(game) => game.to_input_media().into(),
+            Media::Unsupported => return None,
+        })
+    }
+
+    /// Remember the message this media belongs to, which some media needs to make requests.
+    pub(crate) fn in_message(mut self, peer: tl::enums::InputPeer, msg_id: i32) -> Self {
+        if let Media::Poll(poll) = &mut self {
+            poll.message = Some((peer, msg_id));
+        }
+        self
+    }
+
+    /// The raw media, as Telegram sent it.
+    #[cfg(any(test, feature = "serde"))]
+    fn to_raw(&self) -> tl::enums::MessageMedia {
+        match self {
+            Media::Photo(photo) => photo.photo.clone().into(),
+            Media::Document(document) => document.document.clone().into(),
+            Media::Sticker(sticker) => sticker.document.document.clone().into(),
+            Media::Contact(contact) => contact.contact.clone().into(),
+            Media::Geo(geo) => geo.geo.clone().into(),
+            Media::GeoLive(geo) => geo.geo.clone().into(),
+            Media::WebPage(webpage) => (*webpage.webpage).clone().into(),
+            Media::Poll(poll) => poll.poll.clone().into(),
+            Media::Dice(dice) => dice.dice.clone().into(),
+            Media::Venue(venue) => venue.venue.clone().into(),
+            Media::Invoice(invoice) => invoice.invoice.clone().into(),
+            Media::Game(game) => game.game.clone().into(),
+            Media::Unsupported => tl::enums::MessageMedia::Unsupported,
+        }
+    }
+
+    /// Attach the client used to download this media (and any photos or documents within it).
+    ///
+    /// This is only needed for media that was deserialized, since media obtained from a
+    /// message is always attached to the client that received it.
+    pub fn with_client(mut self, client: Client) -> Self {
+        let client = Some(client);
+        match &mut self {
+            Media::Photo(photo) => photo.client = client,
+            Media::Document(document) => document.client = client,
+            Media::Sticker(sticker) => sticker.document.client = client,
+            Media::Geo(geo) => geo.client = client,
+            Media::GeoLive(geo) => geo.client = client,
+            Media::WebPage(webpage) => webpage.client = client,
+            Media::Poll(poll) => poll.client = client,
+            Media::Game(game) => game.client = client,
+            Media::Contact(contact) => contact.client = client,
+            Media::Dice(_) | Media::Venue(_) | Media::Invoice(_) | Media::Unsupported => {}
+        }
+        self
+    }
+
+    /// Whether this media needs a client to be attached with [`Media::with_client`] before it
+    /// can be downloaded (or otherwise used to make requests).
+    ///
+    /// Media that never needs a client, such as a dice, is never detached.
+    pub fn is_detached(&self) -> bool {
+        matches!(self.client_field(), Some(None))
+    }
+
+    /// The client the media is attached to, if any.
+    pub(crate) fn client(&self) -> Option<&Client> {
+        self.client_field().and_then(Option::as_ref)
+    }
+
+    /// Where the client of the media is kept, or `None` for media that never needs one.
+    fn client_field(&self) -> Option<&Option<Client>> {
+        Some(match self {
+            Media::Photo(photo) => &photo.client,
+            Media::Document(document) => &document.client,
+            Media::Sticker(sticker) => &sticker.document.client,
+            Media::Geo(geo) => &geo.client,
+            Media::GeoLive(geo) => &geo.client,
+            Media::WebPage(webpage) => &webpage.client,
+            Media::Poll(poll) => &poll.client,
+            Media::Game(game) => &game.client,
+            Media::Contact(contact) => &contact.client,
+            Media::Dice(_) | Media::Venue(_) | Media::Invoice(_) | Media::Unsupported => {
+                return None
+            }
+        })
+    }
+
+    pub(crate) fn to_input_location(&self) -> Option<tl::enums::InputFileLocation> {
+        match self {
+            Media::Photo(photo) => photo.to_input_location(),
+            Media::Document(document) => document.to_input_location(),
+            Media::Sticker(sticker) => sticker.document.to_input_location(),
+            Media::Contact(_) => None,
             Media::Geo(_) => None,
             Media::GeoLive(_) => None,
+            Media::WebPage(_) => None,
+            Media::Poll(_) => None,
+            Media::Dice(_) => None,
+            Media::Venue(_) => None,
+            Media::Invoice(_) => None,
+            Media::Game(_) => None,
+            Media::Unsupported => None,
         }
     }
 }
@@ -517,3 +2262,1191 @@ impl From<Photo> for Media {
         Self::Photo(photo)
     }
 }
+
+impl From<Document> for Media {
+    fn from(document: Document) -> Self {
+        Self::Document(document)
+    }
+}
+
+impl PartialEq for Photo {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.identity(), other.identity()) {
+            (None, None) => self.photo == other.photo,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl Eq for Photo {}
+
+impl Hash for Photo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state);
+    }
+}
+
+impl PartialEq for Document {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.identity(), other.identity()) {
+            (None, None) => self.document == other.document,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl Eq for Document {}
+
+impl Hash for Document {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state);
+    }
+}
+
+// Only the metadata is shown, so that logging uploads stays readable.
+impl fmt::Debug for Uploaded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Uploaded")
+            .field("name", &self.name())
+            .field("parts", &self.parts())
+            .field("size", &self.size)
+            .field("md5_checksum", &self.md5_checksum())
+            .finish()
+    }
+}
+
+impl PartialEq for Geo {
+    fn eq(&self, other: &Self) -> bool {
+        GeoPoint::raw_key(&self.geo.geo) == GeoPoint::raw_key(&other.geo.geo)
+    }
+}
+
+impl Hash for Geo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        GeoPoint::raw_key(&self.geo.geo).hash(state);
+    }
+}
+
+impl GeoLive {
+    fn key(&self) -> impl Eq + Hash {
+        (
+            GeoPoint::raw_key(&self.geo.geo),
+            self.geo.heading,
+            self.geo.period,
+            self.geo.proximity_notification_radius,
+        )
+    }
+}
+
+impl PartialEq for GeoLive {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Hash for GeoLive {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl Venue {
+    fn key(&self) -> impl Eq + Hash + '_ {
+        (
+            GeoPoint::raw_key(&self.venue.geo),
+            &self.venue.title,
+            &self.venue.address,
+            &self.venue.provider,
+            &self.venue.venue_id,
+            &self.venue.venue_type,
+        )
+    }
+}
+
+impl PartialEq for Venue {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Hash for Venue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialEq for Contact {
+    fn eq(&self, other: &Self) -> bool {
+        self.contact == other.contact
+    }
+}
+
+impl WebPage {
+    fn identity(&self) -> Option<i64> {
+        use tl::enums::WebPage as W;
+
+        match &self.webpage.webpage {
+            W::Empty(page) => Some(page.id),
+            W::Pending(page) => Some(page.id),
+            W::Page(page) => Some(page.id),
+            W::NotModified(_) => None,
+        }
+    }
+}
+
+// The cached page can contain maps with floating point coordinates, so it's not compared.
+impl PartialEq for WebPage {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.identity(), other.identity()) {
+            (None, None) => self.webpage == other.webpage,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl PartialEq for Poll {
+    fn eq(&self, other: &Self) -> bool {
+        self.poll == other.poll
+    }
+}
+
+impl Invoice {
+    // The photo's attributes can contain floating point mask coordinates, so only its URL is
+    // compared.
+    fn key(&self) -> impl PartialEq + '_ {
+        let invoice = &self.invoice;
+        (
+            invoice.photo.as_ref().map(|photo| match photo {
+                tl::enums::WebDocument::Document(photo) => &photo.url,
+                tl::enums::WebDocument::NoProxy(photo) => &photo.url,
+            }),
+            (
+                invoice.shipping_address_requested,
+                invoice.test,
+                &invoice.title,
+                &invoice.description,
+            ),
+            (
+                invoice.receipt_msg_id,
+                &invoice.currency,
+                invoice.total_amount,
+                &invoice.start_param,
+            ),
+        )
+    }
+}
+
+impl PartialEq for Invoice {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl PartialEq for Game {
+    fn eq(&self, other: &Self) -> bool {
+        let tl::enums::Game::Game(game) = &self.game.game;
+        let tl::enums::Game::Game(other) = &other.game.game;
+        (game.id, game.access_hash) == (other.id, other.access_hash)
+    }
+}
+
+impl PartialEq for Uploaded {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity() == other.identity()
+    }
+}
+
+impl Eq for Uploaded {}
+
+impl Hash for Uploaded {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state);
+    }
+}
+
+/// Photos, documents, stickers, web pages and games compare and hash by the object they refer to
+/// in Telegram's servers, which makes it possible to deduplicate media by storing it in a
+/// `HashSet`. Other media compares its fields, with the coordinates of locations and venues
+/// compared by their bits so that equality stays reflexive even for `NaN`. Attached clients, and
+/// the message a poll belongs to, are never compared.
+impl Eq for Media {}
+
+impl Hash for Media {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Media::Photo(photo) => photo.hash(state),
+            Media::Document(document) => document.hash(state),
+            Media::Sticker(sticker) => sticker.document.hash(state),
+            Media::Geo(geo) => geo.hash(state),
+            Media::GeoLive(geo) => geo.hash(state),
+            Media::Venue(venue) => venue.hash(state),
+            // Equal media hashes equally even when only the variant is hashed.
+            _ => {}
+        }
+    }
+}
+
+impl TryFrom<Media> for Photo {
+    type Error = MediaKindError;
+
+    fn try_from(media: Media) -> Result<Self, Self::Error> {
+        match media {
+            Media::Photo(photo) => Ok(photo),
+            media => Err(MediaKindError {
+                expected: MediaKind::Photo,
+                got: media.kind(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Media> for Document {
+    type Error = MediaKindError;
+
+    fn try_from(media: Media) -> Result<Self, Self::Error> {
+        match media {
+            Media::Document(document) => Ok(document),
+            media => Err(MediaKindError {
+                expected: MediaKind::Document,
+                got: media.kind(),
+            }),
+        }
+    }
+}
+
+/// The error type which is returned when converting [`Media`] into a specific kind of media,
+/// such as a [`Photo`], fails because the media is of a different kind.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaKindError {
+    /// The kind of media the conversion was for.
+    pub expected: MediaKind,
+    /// The kind of the media that was converted.
+    pub got: MediaKind,
+}
+
+impl fmt::Display for MediaKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {} media but got {}", self.expected, self.got)
+    }
+}
+
+impl std::error::Error for MediaKindError {}
+
+/// Media is serialized as the raw data Telegram sent, which includes everything needed to
+/// download it again (such as identifiers, access hashes and file references), but not the
+/// client. Deserialized media is detached, and needs [`Media::with_client`] before downloading.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Media {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use tl::Serializable;
+
+        serializer.serialize_bytes(&self.to_raw().to_bytes())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Media {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::{self, SeqAccess, Visitor};
+        use tl::Deserializable;
+
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("the bytes of a serialized media")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(v.to_vec())
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(v)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(bytes)
+            }
+        }
+
+        let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+        let raw = tl::enums::MessageMedia::from_bytes(&bytes).map_err(de::Error::custom)?;
+        Media::from_raw_with(raw, None).ok_or_else(|| de::Error::custom("media is empty"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(round_message: bool) -> tl::enums::DocumentAttribute {
+        tl::types::DocumentAttributeVideo {
+            round_message,
+            supports_streaming: false,
+            duration: 12,
+            w: 640,
+            h: 480,
+        }
+        .into()
+    }
+
+    fn file_name(name: &str) -> tl::enums::DocumentAttribute {
+        tl::types::DocumentAttributeFilename {
+            file_name: name.to_string(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn video_attribute_present() {
+        let attributes = vec![file_name("clip.mp4"), video(true)];
+        let attr = video_attribute(&attributes).unwrap();
+        assert_eq!(attr.duration, 12);
+        assert_eq!((attr.w, attr.h), (640, 480));
+        assert!(attr.round_message);
+    }
+
+    #[test]
+    fn video_attribute_absent() {
+        assert!(video_attribute(&[file_name("notes.txt")]).is_none());
+        assert!(video_attribute(&[]).is_none());
+    }
+
+    #[test]
+    fn audio_attribute_present() {
+        let attributes = vec![
+            file_name("song.mp3"),
+            tl::types::DocumentAttributeAudio {
+                voice: false,
+                duration: 180,
+                title: Some("Title".to_string()),
+                performer: None,
+                waveform: None,
+            }
+            .into(),
+        ];
+        let attr = audio_attribute(&attributes).unwrap();
+        assert_eq!(attr.duration, 180);
+        assert_eq!(attr.title.as_deref(), Some("Title"));
+        assert_eq!(attr.performer.as_deref(), None);
+        assert!(!attr.voice);
+        assert!(audio_attribute(&[video(false)]).is_none());
+    }
+
+    #[test]
+    fn sticker_attribute_with_file_name() {
+        let attributes = vec![
+            file_name("sticker.webp"),
+            tl::types::DocumentAttributeSticker {
+                mask: false,
+                alt: "😀".to_string(),
+                stickerset: tl::enums::InputStickerSet::Empty,
+                mask_coords: None,
+            }
+            .into(),
+        ];
+        assert_eq!(
+            sticker_attribute(&attributes).map(|attr| attr.alt.as_str()),
+            Some("😀")
+        );
+        assert!(sticker_attribute(&[file_name("sticker.webp")]).is_none());
+    }
+
+    #[test]
+    fn animated_attribute() {
+        assert!(is_animated(&[
+            video(false),
+            tl::enums::DocumentAttribute::Animated
+        ]));
+        assert!(!is_animated(&[video(false)]));
+    }
+
+    #[test]
+    fn extension_from_mime_type() {
+        assert_eq!(mime_extension("video/mp4"), Some("mp4"));
+        assert_eq!(mime_extension("application/x-unknown"), None);
+    }
+
+    #[test]
+    fn document_and_photo_extension() {
+        let document = |mime_type: &str| {
+            Document::from_media(
+                tl::types::MessageMediaDocument {
+                    document: Some(
+                        tl::types::Document {
+                            id: 1,
+                            access_hash: 2,
+                            file_reference: Vec::new(),
+                            date: 0,
+                            mime_type: mime_type.to_string(),
+                            size: 0,
+                            thumbs: None,
+                            video_thumbs: None,
+                            dc_id: 2,
+                            attributes: Vec::new(),
+                        }
+                        .into(),
+                    ),
+                    ttl_seconds: None,
+                },
+                None,
+            )
+        };
+
+        assert_eq!(document("audio/ogg").extension(), Some("ogg"));
+        assert_eq!(document("application/x-unknown").extension(), None);
+        assert_eq!(
+            Photo::from_media(
+                tl::types::MessageMediaPhoto {
+                    photo: None,
+                    ttl_seconds: None
+                },
+                None
+            )
+            .extension(),
+            "jpg"
+        );
+    }
+
+    #[test]
+    fn reject_escaping_file_names() {
+        assert!(is_plain_file_name("photo.png"));
+        assert!(!is_plain_file_name("../photo.png"));
+        assert!(!is_plain_file_name(".."));
+        assert!(!is_plain_file_name("a/../../photo.png"));
+        assert!(!is_plain_file_name("/etc/passwd"));
+        assert!(!is_plain_file_name(""));
+    }
+
+    #[test]
+    fn skip_existing_downloads() {
+        assert!(is_already_downloaded(Some(1024), Some(1024)));
+        assert!(!is_already_downloaded(Some(1000), Some(1024)));
+        assert!(!is_already_downloaded(None, Some(1024)));
+        assert!(!is_already_downloaded(Some(1024), None));
+    }
+
+    #[test]
+    fn number_colliding_file_names() {
+        assert_eq!(numbered_file_name("photo.png", 0), "photo.png");
+        assert_eq!(numbered_file_name("photo.png", 2), "photo (2).png");
+        assert_eq!(
+            numbered_file_name("archive.tar.gz", 1),
+            "archive.tar (1).gz"
+        );
+        assert_eq!(numbered_file_name(".bashrc", 1), ".bashrc (1)");
+        assert_eq!(numbered_file_name("README", 1), "README (1)");
+    }
+
+    #[test]
+    fn contact_user_id() {
+        let contact = |user_id| {
+            Contact::from_media(
+                tl::types::MessageMediaContact {
+                    phone_number: "1234".to_string(),
+                    first_name: "First".to_string(),
+                    last_name: String::new(),
+                    vcard: String::new(),
+                    user_id,
+                },
+                None,
+            )
+        };
+        assert_eq!(contact(42).user_id(), Some(42));
+        assert_eq!(contact(0).user_id(), None);
+        assert_eq!(contact(0).first_name(), "First");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn import_contact_without_phone() {
+        let contact = Contact::from_media(
+            tl::types::MessageMediaContact {
+                phone_number: String::new(),
+                first_name: "First".to_string(),
+                last_name: String::new(),
+                vcard: String::new(),
+                user_id: 0,
+            },
+            None,
+        );
+        assert!(matches!(
+            contact.import().await,
+            Err(ImportContactError::NoPhoneNumber)
+        ));
+    }
+
+    #[test]
+    fn dice_value() {
+        let dice = Dice::from_media(tl::types::MessageMediaDice {
+            value: 6,
+            emoticon: "🎲".to_string(),
+        });
+        assert_eq!(dice.value(), 6);
+        assert_eq!(dice.emoticon(), "🎲");
+        assert_eq!(dice.to_input_media().emoticon, "🎲");
+    }
+
+    #[test]
+    fn venue_geo() {
+        let venue = Venue::from_media(tl::types::MessageMediaVenue {
+            geo: tl::types::GeoPoint {
+                long: 2.5,
+                lat: 40.0,
+                access_hash: 0,
+                accuracy_radius: None,
+            }
+            .into(),
+            title: "Cafe".to_string(),
+            address: "Main St. 1".to_string(),
+            provider: String::new(),
+            venue_id: String::new(),
+            venue_type: String::new(),
+        });
+        assert_eq!(venue.title(), "Cafe");
+        assert_eq!(
+            venue.geo(),
+            Some(GeoPoint {
+                longitude: 2.5,
+                latitude: 40.0,
+                accuracy_radius: None,
+            })
+        );
+        assert!(matches!(
+            venue.to_input_media().geo_point,
+            tl::enums::InputGeoPoint::Point(_)
+        ));
+    }
+
+    #[test]
+    fn invoice_fields() {
+        let invoice = Invoice::from_media(tl::types::MessageMediaInvoice {
+            shipping_address_requested: false,
+            test: true,
+            title: "Coffee".to_string(),
+            description: "A cup of coffee".to_string(),
+            photo: None,
+            receipt_msg_id: None,
+            currency: "EUR".to_string(),
+            total_amount: 250,
+            start_param: "coffee".to_string(),
+        });
+        assert_eq!(invoice.title(), "Coffee");
+        assert_eq!(invoice.currency(), "EUR");
+        assert_eq!(invoice.total_amount(), 250);
+        assert!(invoice.is_test());
+        assert!(invoice.photo().is_none());
+    }
+
+    fn document() -> Document {
+        Document::from_media(
+            tl::types::MessageMediaDocument {
+                document: Some(
+                    tl::types::Document {
+                        id: 1,
+                        access_hash: 2,
+                        file_reference: vec![3],
+                        date: 0,
+                        mime_type: "video/mp4".to_string(),
+                        size: 1024,
+                        thumbs: None,
+                        video_thumbs: None,
+                        dc_id: 2,
+                        attributes: vec![file_name("clip.mp4"), video(false)],
+                    }
+                    .into(),
+                ),
+                ttl_seconds: None,
+            },
+            None,
+        )
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn download_document_thumb() {
+        let mut document = document();
+        if let Some(tl::enums::Document::Document(d)) = document.document.document.as_mut() {
+            d.thumbs = Some(vec![
+                tl::types::PhotoStrippedSize {
+                    r#type: "i".to_string(),
+                    bytes: vec![0x01, 0x28, 0x1e, 0xaa],
+                }
+                .into(),
+                tl::types::PhotoCachedSize {
+                    r#type: "m".to_string(),
+                    w: 320,
+                    h: 180,
+                    bytes: vec![0xff; 2048],
+                }
+                .into(),
+            ]);
+        }
+        let path = std::env::temp_dir().join("grammers-download-document-thumb.jpg");
+
+        document.download_thumb(&path, "").await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap().len(), 2048);
+        document.download_thumb(&path, "i").await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap()[..2], [0xff, 0xd8]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            document.download_thumb(&path, "x").await,
+            Err(DownloadError::UnknownThumbType(ty)) if ty == "x"
+        ));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn media_name() {
+        assert_eq!(Media::Document(document()).name(), Some("clip.mp4"));
+        assert_eq!(Media::Unsupported.name(), None);
+    }
+
+    #[test]
+    fn convert_media_into_document() {
+        assert_eq!(
+            Document::try_from(Media::Document(document())),
+            Ok(document())
+        );
+        let error = Photo::try_from(Media::Document(document())).unwrap_err();
+        assert_eq!(
+            error,
+            MediaKindError {
+                expected: MediaKind::Photo,
+                got: MediaKind::Document
+            }
+        );
+        assert_eq!(error.to_string(), "expected photo media but got document");
+    }
+
+    #[test]
+    fn distance_between_cities() {
+        let paris = Geo::from_media(
+            tl::types::MessageMediaGeo {
+                geo: tl::types::GeoPoint {
+                    long: 2.3522,
+                    lat: 48.8566,
+                    access_hash: 0,
+                    accuracy_radius: None,
+                }
+                .into(),
+            },
+            None,
+        );
+        let new_york = GeoPoint {
+            latitude: 40.7128,
+            longitude: -74.0060,
+            accuracy_radius: None,
+        };
+
+        let london = paris.distance_to(51.5074, -0.1278).unwrap();
+        assert!((london - 343_500.0).abs() < 2_000.0, "{}", london);
+        let los_angeles = new_york.distance_to(34.0522, -118.2437);
+        assert!(
+            (los_angeles - 3_936_000.0).abs() < 10_000.0,
+            "{}",
+            los_angeles
+        );
+        assert_eq!(new_york.distance_to(40.7128, -74.0060), 0.0);
+
+        let unknown = Geo::from_media(
+            tl::types::MessageMediaGeo {
+                geo: tl::enums::GeoPoint::Empty,
+            },
+            None,
+        );
+        assert_eq!(unknown.distance_to(0.0, 0.0), None);
+    }
+
+    #[test]
+    fn webpage_document() {
+        let webpage = WebPage::from_media(
+            tl::types::MessageMediaWebPage {
+                webpage: tl::types::WebPage {
+                    id: 1,
+                    url: "https://example.com/clip".to_string(),
+                    display_url: "example.com/clip".to_string(),
+                    hash: 0,
+                    r#type: Some("video".to_string()),
+                    site_name: None,
+                    title: None,
+                    description: None,
+                    photo: None,
+                    embed_url: None,
+                    embed_type: None,
+                    embed_width: None,
+                    embed_height: None,
+                    duration: None,
+                    author: None,
+                    document: document().document.document,
+                    cached_page: None,
+                    attributes: None,
+                }
+                .into(),
+            },
+            None,
+        );
+        assert_eq!(webpage.document(), Some(document()));
+        assert_eq!(webpage.photo(), None);
+    }
+
+    #[test]
+    fn compare_media_without_volatile_fields() {
+        let mut voted = poll();
+        voted.message = Some((tl::enums::InputPeer::PeerSelf, 1));
+        assert_eq!(Media::Poll(voted), Media::Poll(poll()));
+
+        let page = |title: &str| {
+            Media::WebPage(WebPage::from_media(
+                tl::types::MessageMediaWebPage {
+                    webpage: tl::types::WebPage {
+                        id: 1,
+                        url: "https://example.com".to_string(),
+                        display_url: "example.com".to_string(),
+                        hash: 0,
+                        r#type: None,
+                        site_name: None,
+                        title: Some(title.to_string()),
+                        description: None,
+                        photo: None,
+                        embed_url: None,
+                        embed_type: None,
+                        embed_width: None,
+                        embed_height: None,
+                        duration: None,
+                        author: None,
+                        document: None,
+                        cached_page: None,
+                        attributes: None,
+                    }
+                    .into(),
+                },
+                None,
+            ))
+        };
+        assert_eq!(page("Example"), page("Example Domain"));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn download_returning_path_into_dir() {
+        let dir = std::env::temp_dir().join("grammers-download-returning-path");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("clip.mp4"), b"existing").unwrap();
+
+        // Detached documents can't be downloaded, so no file is reserved for them either.
+        assert!(matches!(
+            document().download_returning_path(&dir).await,
+            Err(DownloadError::Detached)
+        ));
+        let mut names = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        names.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(names, vec!["clip.mp4"]);
+    }
+
+    #[test]
+    fn media_kind() {
+        assert_eq!(Media::Document(document()).kind(), MediaKind::Document);
+        assert_eq!(Media::Unsupported.kind(), MediaKind::Unsupported);
+        assert_eq!(MediaKind::GeoLive.to_string(), "live geo");
+    }
+
+    #[test]
+    fn media_size() {
+        assert_eq!(Media::Document(document()).size(), Some(1024));
+        assert_eq!(Media::Unsupported.size(), None);
+    }
+
+    #[test]
+    fn raw_document_attributes() {
+        assert_eq!(
+            document().attributes(),
+            &[file_name("clip.mp4"), video(false)][..]
+        );
+        let empty = Document::from_media(
+            tl::types::MessageMediaDocument {
+                document: None,
+                ttl_seconds: None,
+            },
+            None,
+        );
+        assert!(empty.attributes().is_empty());
+    }
+
+    #[test]
+    // The client is the only interior-mutable part of media, and it is not hashed.
+    #[allow(clippy::mutable_key_type)]
+    fn dedupe_media_by_identity() {
+        let refreshed = {
+            let mut document = document();
+            if let Some(tl::enums::Document::Document(d)) = document.document.document.as_mut() {
+                d.file_reference = vec![4];
+            }
+            document
+        };
+        let other = {
+            let mut document = document();
+            if let Some(tl::enums::Document::Document(d)) = document.document.document.as_mut() {
+                d.id = 5;
+            }
+            document
+        };
+        assert_eq!(refreshed, document());
+
+        let set = vec![document(), refreshed, other]
+            .into_iter()
+            .map(Media::Document)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn nan_location_equals_itself() {
+        let geo = |lat: f64| {
+            Media::Geo(Geo::from_media(
+                tl::types::MessageMediaGeo {
+                    geo: tl::types::GeoPoint {
+                        long: 0.0,
+                        lat,
+                        access_hash: 0,
+                        accuracy_radius: None,
+                    }
+                    .into(),
+                },
+                None,
+            ))
+        };
+        assert_eq!(geo(f64::NAN), geo(f64::NAN));
+        assert_ne!(geo(0.0), geo(-0.0));
+
+        let set = vec![geo(f64::NAN), geo(f64::NAN), geo(1.0)]
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn raw_media_round_trip() {
+        let media = Media::Document(document());
+        let restored = Media::from_raw_with(media.to_raw(), None).unwrap();
+        assert_eq!(restored, media);
+        assert!(restored.is_detached());
+        assert!(!Media::Unsupported.is_detached());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_media_round_trip() {
+        use serde::Deserialize as _;
+
+        let photo = Photo::from_photo(
+            tl::types::Photo {
+                has_stickers: false,
+                id: 1,
+                access_hash: 2,
+                file_reference: vec![3],
+                date: 4,
+                sizes: vec![tl::types::PhotoSize {
+                    r#type: "y".to_string(),
+                    w: 1280,
+                    h: 960,
+                    size: 84213,
+                }
+                .into()],
+                video_sizes: None,
+                dc_id: 2,
+            }
+            .into(),
+            None,
+        );
+        let contact = Contact::from_media(
+            tl::types::MessageMediaContact {
+                phone_number: "1234".to_string(),
+                first_name: "First".to_string(),
+                last_name: String::new(),
+                vcard: String::new(),
+                user_id: 42,
+            },
+            None,
+        );
+
+        for media in [
+            Media::Photo(photo),
+            Media::Document(document()),
+            Media::Contact(contact),
+        ] {
+            let value = toml::Value::try_from(&media).unwrap();
+            let restored = Media::deserialize(value).unwrap();
+            assert_eq!(restored, media);
+            assert!(restored.is_detached());
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn detached_media_cannot_download() {
+        let mut buffer = Vec::new();
+        assert!(matches!(
+            document().download_into(&mut buffer).await,
+            Err(DownloadError::Detached)
+        ));
+        assert!(buffer.is_empty());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn detached_media_keeps_existing_file() {
+        let path = std::env::temp_dir().join("grammers-detached-media-keeps-file.mp4");
+        std::fs::write(&path, b"existing").unwrap();
+
+        assert!(matches!(
+            document().download(&path).await,
+            Err(DownloadError::Detached)
+        ));
+        assert!(matches!(
+            document()
+                .download_with_options(&path, &DownloadOptions::default())
+                .await,
+            Err(DownloadError::Detached)
+        ));
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, b"existing");
+    }
+
+    #[test]
+    fn uploaded_name_and_size() {
+        let uploaded = Uploaded::from_raw(
+            tl::types::InputFileBig {
+                id: 1,
+                parts: 3,
+                name: "clip.mp4".to_string(),
+            }
+            .into(),
+        );
+        assert_eq!(uploaded.name(), "clip.mp4");
+        assert_eq!(uploaded.size(), None);
+        assert_eq!(uploaded.md5_checksum(), None);
+        assert_eq!(uploaded.with_size(1500).size(), Some(1500));
+    }
+
+    #[test]
+    fn debug_uploaded_metadata() {
+        let uploaded = Uploaded::from_raw(
+            tl::types::InputFileBig {
+                id: 123456789,
+                parts: 4,
+                name: "clip.mp4".to_string(),
+            }
+            .into(),
+        )
+        .with_size(2000000);
+        assert_eq!(
+            format!("{:?}", uploaded),
+            r#"Uploaded { name: "clip.mp4", parts: 4, size: Some(2000000), md5_checksum: None }"#
+        );
+    }
+
+    #[test]
+    fn uploaded_into_input_media() {
+        let uploaded = || {
+            Uploaded::from_raw(
+                tl::types::InputFileBig {
+                    id: 1,
+                    parts: 4,
+                    name: "clip.mp4".to_string(),
+                }
+                .into(),
+            )
+        };
+        assert!(matches!(
+            uploaded().into_input_media_photo(),
+            tl::enums::InputMedia::UploadedPhoto(_)
+        ));
+
+        match uploaded().into_input_media_document("video/mp4", vec![]) {
+            tl::enums::InputMedia::UploadedDocument(document) => {
+                assert_eq!(document.mime_type, "video/mp4");
+                assert_eq!(document.attributes, vec![file_name("clip.mp4")]);
+            }
+            _ => panic!("expected an uploaded document"),
+        }
+        match uploaded().into_input_media_document(
+            "video/mp4",
+            vec![Attribute::FileName("trip.mp4".to_string())],
+        ) {
+            tl::enums::InputMedia::UploadedDocument(document) => {
+                assert_eq!(document.attributes, vec![file_name("trip.mp4")]);
+            }
+            _ => panic!("expected an uploaded document"),
+        }
+    }
+
+    #[test]
+    fn uploaded_small_file_checksum() {
+        let uploaded = Uploaded::from_raw(
+            tl::types::InputFile {
+                id: 1,
+                parts: 1,
+                name: "notes.txt".to_string(),
+                md5_checksum: "d41d8cd98f00b204e9800998ecf8427e".to_string(),
+            }
+            .into(),
+        );
+        assert_eq!(
+            uploaded.md5_checksum(),
+            Some("d41d8cd98f00b204e9800998ecf8427e")
+        );
+    }
+
+    fn poll() -> Poll {
+        Poll::from_media(
+            tl::types::MessageMediaPoll {
+                poll: tl::types::Poll {
+                    id: 1,
+                    closed: false,
+                    public_voters: false,
+                    multiple_choice: false,
+                    quiz: false,
+                    question: "?".to_string(),
+                    answers: vec![],
+                    close_period: None,
+                    close_date: None,
+                }
+                .into(),
+                results: tl::types::PollResults {
+                    min: false,
+                    results: None,
+                    total_voters: None,
+                    recent_voters: None,
+                    solution: None,
+                    solution_entities: None,
+                }
+                .into(),
+            },
+            None,
+        )
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn vote_in_detached_poll() {
+        let poll = poll();
+
+        assert!(matches!(poll.vote(&[]).await, Err(PollError::NoOptions)));
+        assert!(matches!(poll.vote(&[&[0]]).await, Err(PollError::Detached)));
+        assert!(matches!(
+            poll.retract_vote().await,
+            Err(PollError::Detached)
+        ));
+        assert!(matches!(poll.close().await, Err(PollError::Detached)));
+    }
+
+    #[test]
+    fn close_quiz_with_correct_answers() {
+        let answer = |option: u8| {
+            tl::types::PollAnswerVoters {
+                chosen: false,
+                correct: option == 1,
+                option: vec![option],
+                voters: 3,
+            }
+            .into()
+        };
+        let poll = Poll::from_media(
+            tl::types::MessageMediaPoll {
+                poll: tl::types::Poll {
+                    id: 1,
+                    closed: false,
+                    public_voters: false,
+                    multiple_choice: false,
+                    quiz: true,
+                    question: "?".to_string(),
+                    answers: vec![],
+                    close_period: None,
+                    close_date: None,
+                }
+                .into(),
+                results: tl::types::PollResults {
+                    min: false,
+                    results: Some(vec![answer(0), answer(1)]),
+                    total_voters: Some(6),
+                    recent_voters: None,
+                    solution: None,
+                    solution_entities: None,
+                }
+                .into(),
+            },
+            None,
+        );
+
+        let media = poll.to_closed_input_media();
+        let tl::enums::Poll::Poll(closed) = media.poll;
+        assert!(closed.closed);
+        assert_eq!(media.correct_answers, Some(vec![vec![1]]));
+    }
+
+    #[test]
+    fn find_poll_results_in_updates() {
+        let results = |total_voters| {
+            tl::types::PollResults {
+                min: false,
+                results: None,
+                total_voters: Some(total_voters),
+                recent_voters: None,
+                solution: None,
+                solution_entities: None,
+            }
+            .into()
+        };
+        let updates = tl::types::Updates {
+            updates: vec![
+                tl::types::UpdateMessagePoll {
+                    poll_id: 1,
+                    poll: None,
+                    results: results(10),
+                }
+                .into(),
+                tl::types::UpdateMessagePoll {
+                    poll_id: 2,
+                    poll: None,
+                    results: results(20),
+                }
+                .into(),
+            ],
+            users: vec![],
+            chats: vec![],
+            date: 0,
+            seq: 0,
+        }
+        .into();
+
+        let found = PollResults::from_updates(&updates, 2).unwrap();
+        assert_eq!(found.total_voters, Some(20));
+        assert!(PollResults::from_updates(&updates, 3).is_none());
+        assert!(PollResults::from_updates(&tl::enums::Updates::TooLong, 1).is_none());
+    }
+
+    #[test]
+    fn resend_media_without_server_reference() {
+        let dice = Media::Dice(Dice::from_media(tl::types::MessageMediaDice {
+            value: 1,
+            emoticon: "🎯".to_string(),
+        }));
+        assert!(matches!(
+            dice.to_input_media(),
+            Some(tl::enums::InputMedia::Dice(_))
+        ));
+        assert_eq!(Media::Unsupported.to_input_media(), None);
+    }
+}
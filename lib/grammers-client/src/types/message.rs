@@ -9,7 +9,7 @@ use crate::types::media::Document;
 use crate::types::{InputMessage, Media, Photo};
 use crate::utils;
 use crate::ChatMap;
-use crate::{types, Client};
+use crate::{types, Client, DownloadError};
 use grammers_mtsender::InvocationError;
 use grammers_tl_types as tl;
 use std::io;
@@ -279,6 +279,10 @@ impl Message {
             .media
             .clone()
             .and_then(|x| Media::from_raw(x, self.client.clone()))
+            .map(|media| match media {
+                Media::Poll(_) => media.in_message(self.chat().to_input_peer(), self.msg.id),
+                media => media,
+            })
     }
 
     /// If the message has a reply markup (which can happen for messages produced by bots),
@@ -476,14 +480,42 @@ impl Message {
     ///
     /// Returns `true` if there was media to download, or `false` otherwise.
     ///
+    /// If the file reference of the media has expired, the message is fetched again (updating
+    /// it in-place) to get a fresh reference, and the download is retried once.
+    ///
     /// Shorthand for `Client::download_media`.
     pub async fn download_media<P: AsRef<Path>>(&mut self, path: P) -> Result<bool, io::Error> {
-        // TODO probably encode failed download in error
-        if let Some(media) = self.media() {
-            self.client.download_media(&media, path).await.map(|_| true)
-        } else {
-            Ok(false)
+        let path = path.as_ref();
+        let location = match self.media().and_then(|media| media.to_input_location()) {
+            Some(location) => location,
+            None => return Ok(false),
+        };
+
+        match self.client.download_media_at_location(location, path).await {
+            Err(DownloadError::FileReferenceExpired) => {}
+            result => return result.map(|_| true).map_err(io::Error::from),
         }
+
+        let message = self
+            .client
+            .get_messages_by_id(&self.chat(), &[self.msg.id])
+            .await
+            .map_err(DownloadError::from)?
+            .pop()
+            .flatten();
+        let location = match message {
+            Some(message) => {
+                *self = message;
+                self.media().and_then(|media| media.to_input_location())
+            }
+            None => None,
+        };
+
+        let location = location.ok_or(DownloadError::FileReferenceExpired)?;
+        self.client
+            .download_media_at_location(location, path)
+            .await?;
+        Ok(true)
     }
 
     /// Get photo attached to the message if any.
@@ -14,6 +14,7 @@ pub mod chat;
 pub mod chat_map;
 pub mod chats;
 pub mod dialog;
+pub mod downloadable;
 pub mod inline_query;
 pub mod input_message;
 pub mod iter_buffer;
@@ -35,6 +36,7 @@ pub use chat_map::ChatMap;
 pub(crate) use chat_map::Peer;
 pub use chats::{AdminRightsBuilder, BannedRightsBuilder};
 pub use dialog::Dialog;
+pub use downloadable::Downloadable;
 pub use inline_query::InlineQuery;
 pub use input_message::InputMessage;
 pub use iter_buffer::IterBuffer;
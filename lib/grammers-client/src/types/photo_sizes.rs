@@ -5,15 +5,162 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+use bytes::Bytes;
+use futures_util::future::Either;
+use futures_util::stream::{self, Stream};
+use std::cmp::{Ordering, Reverse};
+use std::fmt;
 use std::path::Path;
 
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tokio::{fs, io};
 
 use grammers_tl_types as tl;
 
-use crate::Client;
+use crate::client::files::attached;
+use crate::{Client, DownloadError, DownloadOptions};
 
+// JPEG header shared by all stripped thumbnails. The bytes at offsets 164 and 166 are the
+// height and width, which are taken from the stripped payload itself.
+const STRIPPED_HEADER: [u8; 623] = [
+    0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10, 0x4a, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01, 0x00, 0x00, 0x01,
+    0x00, 0x01, 0x00, 0x00, 0xff, 0xdb, 0x00, 0x43, 0x00, 0x28, 0x1c, 0x1e, 0x23, 0x1e, 0x19, 0x28,
+    0x23, 0x21, 0x23, 0x2d, 0x2b, 0x28, 0x30, 0x3c, 0x64, 0x41, 0x3c, 0x37, 0x37, 0x3c, 0x7b, 0x58,
+    0x5d, 0x49, 0x64, 0x91, 0x80, 0x99, 0x96, 0x8f, 0x80, 0x8c, 0x8a, 0xa0, 0xb4, 0xe6, 0xc3, 0xa0,
+    0xaa, 0xda, 0xad, 0x8a, 0x8c, 0xc8, 0xff, 0xcb, 0xda, 0xee, 0xf5, 0xff, 0xff, 0xff, 0x9b, 0xc1,
+    0xff, 0xff, 0xff, 0xfa, 0xff, 0xe6, 0xfd, 0xff, 0xf8, 0xff, 0xdb, 0x00, 0x43, 0x01, 0x2b, 0x2d,
+    0x2d, 0x3c, 0x35, 0x3c, 0x76, 0x41, 0x41, 0x76, 0xf8, 0xa5, 0x8c, 0xa5, 0xf8, 0xf8, 0xf8, 0xf8,
+    0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8,
+    0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8,
+    0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xff, 0xc0,
+    0x00, 0x11, 0x08, 0x00, 0x00, 0x00, 0x00, 0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11,
+    0x01, 0xff, 0xc4, 0x00, 0x1f, 0x00, 0x00, 0x01, 0x05, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09,
+    0x0a, 0x0b, 0xff, 0xc4, 0x00, 0xb5, 0x10, 0x00, 0x02, 0x01, 0x03, 0x03, 0x02, 0x04, 0x03, 0x05,
+    0x05, 0x04, 0x04, 0x00, 0x00, 0x01, 0x7d, 0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21,
+    0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23,
+    0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17,
+    0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a,
+    0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a,
+    0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a,
+    0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99,
+    0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7,
+    0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5,
+    0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1,
+    0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xff, 0xc4, 0x00, 0x1f, 0x01, 0x00, 0x03,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0xff, 0xc4, 0x00, 0xb5, 0x11, 0x00,
+    0x02, 0x01, 0x02, 0x04, 0x04, 0x03, 0x04, 0x07, 0x05, 0x04, 0x04, 0x00, 0x01, 0x02, 0x77, 0x00,
+    0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71, 0x13,
+    0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0, 0x15,
+    0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26, 0x27,
+    0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88,
+    0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6,
+    0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4,
+    0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9,
+    0xfa, 0xff, 0xda, 0x00, 0x0c, 0x03, 0x01, 0x00, 0x02, 0x11, 0x03, 0x11, 0x00, 0x3f, 0x00,
+];
+const STRIPPED_FOOTER: [u8; 2] = [0xff, 0xd9];
+
+/// Inflate the payload of a stripped thumbnail into a complete JPEG image.
+///
+/// Stripped thumbnails are sent as JPEG images with their fixed header and footer removed to
+/// save space, so the returned image will always be very small (around 40x40).
+///
+/// Returns an empty vector if the payload is not a valid stripped thumbnail.
+///
+/// Based on https://core.telegram.org/api/files#stripped-thumbnails
+pub fn expand_stripped_thumbnail(stripped: &[u8]) -> Vec<u8> {
+    if stripped.len() < 3 || stripped[0] != 0x01 {
+        return Vec::new();
+    }
+
+    let mut real =
+        Vec::with_capacity(STRIPPED_HEADER.len() + stripped.len() - 3 + STRIPPED_FOOTER.len());
+    real.extend_from_slice(&STRIPPED_HEADER);
+    real[164] = stripped[1];
+    real[166] = stripped[2];
+    real.extend_from_slice(&stripped[3..]);
+    real.extend_from_slice(&STRIPPED_FOOTER);
+    real
+}
+
+async fn write_bytes_into<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    bytes: &[u8],
+) -> Result<u64, DownloadError> {
+    writer.write_all(bytes).await?;
+    writer.flush().await?;
+    Ok(bytes.len() as u64)
+}
+
+/// The photo or document a server-side thumbnail belongs to.
+///
+/// The file reference is shared by all the thumbnails of the same owner, so it's kept as
+/// [`Bytes`], which can be cloned without copying.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ThumbOwner {
+    Photo {
+        id: i64,
+        access_hash: i64,
+        file_reference: Bytes,
+    },
+    Document {
+        id: i64,
+        access_hash: i64,
+        file_reference: Bytes,
+    },
+}
+
+impl ThumbOwner {
+    pub(crate) fn from_photo(photo: &tl::types::Photo) -> Self {
+        ThumbOwner::Photo {
+            id: photo.id,
+            access_hash: photo.access_hash,
+            file_reference: Bytes::copy_from_slice(&photo.file_reference),
+        }
+    }
+
+    pub(crate) fn from_document(document: &tl::types::Document) -> Self {
+        ThumbOwner::Document {
+            id: document.id,
+            access_hash: document.access_hash,
+            file_reference: Bytes::copy_from_slice(&document.file_reference),
+        }
+    }
+
+    fn to_input_location(&self, thumb_size: &str) -> tl::enums::InputFileLocation {
+        match self {
+            ThumbOwner::Photo {
+                id,
+                access_hash,
+                file_reference,
+            } => tl::types::InputPhotoFileLocation {
+                id: *id,
+                access_hash: *access_hash,
+                file_reference: file_reference.to_vec(),
+                thumb_size: thumb_size.to_string(),
+            }
+            .into(),
+            ThumbOwner::Document {
+                id,
+                access_hash,
+                file_reference,
+            } => tl::types::InputDocumentFileLocation {
+                id: *id,
+                access_hash: *access_hash,
+                file_reference: file_reference.to_vec(),
+                thumb_size: thumb_size.to_string(),
+            }
+            .into(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
 pub enum PhotoSize {
     Empty(SizeEmpty),
     Size(Size),
@@ -26,8 +173,8 @@ pub enum PhotoSize {
 impl PhotoSize {
     pub(crate) fn make_from(
         size: &tl::enums::PhotoSize,
-        photo: &tl::types::Photo,
-        client: Client,
+        owner: &ThumbOwner,
+        client: Option<Client>,
     ) -> Self {
         match size {
             tl::enums::PhotoSize::Empty(size) => PhotoSize::Empty(SizeEmpty {
@@ -38,9 +185,7 @@ impl PhotoSize {
                 width: size.w,
                 height: size.h,
                 size: size.size,
-                id: photo.id,
-                access_hash: photo.access_hash,
-                file_reference: photo.file_reference.clone(),
+                owner: owner.clone(),
                 client,
             }),
             tl::enums::PhotoSize::PhotoCachedSize(size) => PhotoSize::Cached(CachedSize {
@@ -58,6 +203,8 @@ impl PhotoSize {
                 width: size.w,
                 height: size.h,
                 sizes: size.sizes.clone(),
+                owner: owner.clone(),
+                client,
             }),
             tl::enums::PhotoSize::PhotoPathSize(size) => PhotoSize::Path(PathSize {
                 photo_type: size.r#type.clone(),
@@ -79,11 +226,26 @@ impl PhotoSize {
                 }
                 size.bytes.len() + 622
             }
-            PhotoSize::Progressive(size) => size.sizes.iter().sum::<i32>() as usize,
+            PhotoSize::Progressive(size) => size.sizes.last().copied().unwrap_or(0) as usize,
             PhotoSize::Path(size) => size.bytes.len(),
         }
     }
 
+    /// Width and height of the photo thumb, if known.
+    ///
+    /// Stripped thumbnails have their dimensions decoded from the payload, while empty and
+    /// vector thumbnails have none.
+    pub fn dimensions(&self) -> Option<(i32, i32)> {
+        match self {
+            PhotoSize::Empty(_) => None,
+            PhotoSize::Size(size) => Some((size.width, size.height)),
+            PhotoSize::Cached(size) => Some((size.width, size.height)),
+            PhotoSize::Stripped(size) => size.dimensions(),
+            PhotoSize::Progressive(size) => Some((size.width, size.height)),
+            PhotoSize::Path(_) => None,
+        }
+    }
+
     /// Download the photo thumb into the defined location
     ///
     /// # Examples
@@ -95,130 +257,161 @@ impl PhotoSize {
     ///   message.photo().unwrap().thumbs().largest().unwrap().download(location).await;
     /// }
     /// ```
-    pub async fn download<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+    pub async fn download<P: AsRef<Path>>(&self, path: P) -> Result<(), DownloadError> {
+        self.download_with_progress(path, |_, _| {}).await
+    }
+
+    /// Download the photo thumb into the file at the given path, reporting the progress.
+    ///
+    /// After every chunk is written, `progress` is called with the number of bytes downloaded so
+    /// far, and the total size of the thumb if known. Thumbs already in memory are written (and
+    /// reported) at once.
+    pub async fn download_with_progress<P, F>(
+        &self,
+        path: P,
+        progress: F,
+    ) -> Result<(), DownloadError>
+    where
+        P: AsRef<Path>,
+        F: FnMut(u64, Option<u64>),
+    {
+        self.check_downloadable()?;
+        let mut file = fs::File::create(path).await?;
+        self.write_into(&mut file, &DownloadOptions::default(), progress)
+            .await?;
+        Ok(())
+    }
+
+    /// Download the photo thumb into the file at the given path, using the given options.
+    ///
+    /// The options are checked before the file is created, even for thumbs which are already
+    /// in memory and don't need to be fetched.
+    pub async fn download_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &DownloadOptions,
+    ) -> Result<(), DownloadError> {
+        options.check()?;
+        self.check_downloadable()?;
+        let mut file = fs::File::create(path).await?;
+        self.write_into(&mut file, options, |_, _| {}).await?;
+        Ok(())
+    }
+
+    /// Download the photo thumb into the given writer, returning how many bytes were written.
+    ///
+    /// Sizes which need to be fetched from Telegram are streamed into the writer chunk by chunk,
+    /// while the rest are written at once from the data already in memory.
+    ///
+    /// # Examples
+    /// ```
+    /// # use grammers_client::types::Message;
+    /// use grammers_client::types::photo_sizes::VecExt;
+    /// async fn load_photo(mut message: Message) -> Vec<u8> {
+    ///   let mut buffer = Vec::new();
+    ///   message.photo().unwrap().thumbs().largest().unwrap().download_into(&mut buffer).await;
+    ///   buffer
+    /// }
+    /// ```
+    pub async fn download_into<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+    ) -> Result<u64, DownloadError> {
+        self.write_into(writer, &DownloadOptions::default(), |_, _| {})
+            .await
+    }
+
+    /// Fail if the thumb can't be downloaded, which is reported before creating any file.
+    ///
+    /// `Empty` sizes have nothing to download, and sizes which need to be fetched from Telegram
+    /// need a client attached.
+    pub(crate) fn check_downloadable(&self) -> Result<(), DownloadError> {
         match self {
-            PhotoSize::Empty(_) => fs::File::create(path).await.map(|_| ()),
+            PhotoSize::Empty(_) => Err(DownloadError::NoSize),
+            PhotoSize::Size(size) => attached(&size.client).map(drop),
+            PhotoSize::Progressive(size) => attached(&size.client).map(drop),
+            PhotoSize::Cached(_) | PhotoSize::Stripped(_) | PhotoSize::Path(_) => Ok(()),
+        }
+    }
+
+    async fn write_into<W, F>(
+        &self,
+        writer: &mut W,
+        options: &DownloadOptions,
+        mut progress: F,
+    ) -> Result<u64, DownloadError>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(u64, Option<u64>),
+    {
+        let total = match self.size() {
+            0 => None,
+            size => Some(size as u64),
+        };
+        let bytes = match self {
+            PhotoSize::Empty(_) => return Err(DownloadError::NoSize),
             PhotoSize::Size(size) => {
-                let input_location = tl::types::InputPhotoFileLocation {
-                    id: size.id,
-                    access_hash: size.access_hash,
-                    file_reference: size.file_reference.clone(),
-                    thumb_size: size.photo_type.clone(),
-                };
-                size.client
-                    .clone()
-                    .download_media_at_location(input_location.into(), path)
+                return attached(&size.client)?
+                    .download_location_into(
+                        size.to_input_location(),
+                        writer,
+                        options,
+                        total,
+                        progress,
+                    )
                     .await
             }
-            PhotoSize::Cached(size) => {
-                let mut file = fs::File::create(path).await.unwrap();
-                file.write(&size.bytes).await.map(|_| ())
+            PhotoSize::Progressive(size) => {
+                return attached(&size.client)?
+                    .download_location_into(
+                        size.to_input_location(),
+                        writer,
+                        options,
+                        total,
+                        progress,
+                    )
+                    .await
             }
-            PhotoSize::Stripped(size) => {
-                // Based on https://core.tlgr.org/api/files#stripped-thumbnails
-                let bytes = &size.bytes;
-                if bytes.len() < 3 || bytes[0] != 0x01 {
-                    return Ok(());
-                }
+            PhotoSize::Cached(size) => size.bytes.clone(),
+            PhotoSize::Stripped(size) => expand_stripped_thumbnail(&size.bytes),
+            PhotoSize::Path(size) => size.to_svg().into_bytes(),
+        };
 
-                let header = vec![
-                    0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10, 0x4a, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01,
-                    0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0xff, 0xdb, 0x00, 0x43, 0x00, 0x28,
-                    0x1c, 0x1e, 0x23, 0x1e, 0x19, 0x28, 0x23, 0x21, 0x23, 0x2d, 0x2b, 0x28, 0x30,
-                    0x3c, 0x64, 0x41, 0x3c, 0x37, 0x37, 0x3c, 0x7b, 0x58, 0x5d, 0x49, 0x64, 0x91,
-                    0x80, 0x99, 0x96, 0x8f, 0x80, 0x8c, 0x8a, 0xa0, 0xb4, 0xe6, 0xc3, 0xa0, 0xaa,
-                    0xda, 0xad, 0x8a, 0x8c, 0xc8, 0xff, 0xcb, 0xda, 0xee, 0xf5, 0xff, 0xff, 0xff,
-                    0x9b, 0xc1, 0xff, 0xff, 0xff, 0xfa, 0xff, 0xe6, 0xfd, 0xff, 0xf8, 0xff, 0xdb,
-                    0x00, 0x43, 0x01, 0x2b, 0x2d, 0x2d, 0x3c, 0x35, 0x3c, 0x76, 0x41, 0x41, 0x76,
-                    0xf8, 0xa5, 0x8c, 0xa5, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8,
-                    0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8,
-                    0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8,
-                    0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8, 0xf8,
-                    0xf8, 0xf8, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x00, 0x00, 0x00, 0x00, 0x03, 0x01,
-                    0x22, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11, 0x01, 0xff, 0xc4, 0x00, 0x1f, 0x00,
-                    0x00, 0x01, 0x05, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09,
-                    0x0a, 0x0b, 0xff, 0xc4, 0x00, 0xb5, 0x10, 0x00, 0x02, 0x01, 0x03, 0x03, 0x02,
-                    0x04, 0x03, 0x05, 0x05, 0x04, 0x04, 0x00, 0x00, 0x01, 0x7d, 0x01, 0x02, 0x03,
-                    0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
-                    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15,
-                    0x52, 0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18,
-                    0x19, 0x1a, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38,
-                    0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55,
-                    0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a,
-                    0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87,
-                    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2,
-                    0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6,
-                    0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca,
-                    0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2, 0xe3, 0xe4,
-                    0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7,
-                    0xf8, 0xf9, 0xfa, 0xff, 0xc4, 0x00, 0x1f, 0x01, 0x00, 0x03, 0x01, 0x01, 0x01,
-                    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
-                    0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0xff, 0xc4, 0x00,
-                    0xb5, 0x11, 0x00, 0x02, 0x01, 0x02, 0x04, 0x04, 0x03, 0x04, 0x07, 0x05, 0x04,
-                    0x04, 0x00, 0x01, 0x02, 0x77, 0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21,
-                    0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71, 0x13, 0x22, 0x32, 0x81, 0x08,
-                    0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0, 0x15, 0x62,
-                    0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a,
-                    0x26, 0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44,
-                    0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59,
-                    0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76,
-                    0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a,
-                    0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
-                    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9,
-                    0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4,
-                    0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8,
-                    0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xff, 0xda,
-                    0x00, 0x0c, 0x03, 0x01, 0x00, 0x02, 0x11, 0x03, 0x11, 0x00, 0x3f, 0x00,
-                ];
-                let mut footer = vec![0xff, 0xd9];
-                let mut real = header;
-                real[164] = bytes[1];
-                real[166] = bytes[2];
-
-                let mut bytes_clone = bytes.clone()[3..].to_vec();
-                real.append(&mut bytes_clone);
-                real.append(&mut footer);
-
-                let mut file = fs::File::create(path).await.unwrap();
-                file.write(&real).await.map(|_| ())
-            }
-            PhotoSize::Progressive(_) => {
-                // Nothing
-                Ok(())
-            }
-            PhotoSize::Path(size) => {
-                // Based on https://core.tlgr.org/api/files#vector-thumbnails
-                let lookup = "AACAAAAHAAALMAAAQASTAVAAAZaacaaaahaaalmaaaqastava.az0123456789-,";
-                let mut path = String::from("M");
-                for num in &size.bytes {
-                    let num = *num;
-                    if num >= 128 + 64 {
-                        path.push(lookup.chars().nth((num - 128 - 64) as usize).unwrap());
-                    } else {
-                        if num >= 128 {
-                            path.push(',');
-                        } else if num >= 64 {
-                            path.push('-');
-                        }
-                        path.push((num & 63) as char);
-                    }
-                }
-                path.push('z');
-                let res = format!(
-                    r###"<?xml version="1.0" encoding="utf-8"?>
-  <svg version="1.1" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"
-     viewBox="0 0 512 512" xml:space="preserve">
-  <path d="{}"/>
-</svg>"###,
-                    path
-                );
-                let mut file = fs::File::create(path).await.unwrap();
-                file.write(res.as_bytes()).await.map(|_| ())
+        let written = write_bytes_into(writer, &bytes).await?;
+        progress(written, total);
+        Ok(written)
+    }
+
+    /// Download the photo thumb fully into memory.
+    ///
+    /// Only sizes which are not already in memory require a request to Telegram. An `Empty`
+    /// size results in an empty buffer.
+    pub async fn bytes(&self) -> Result<Vec<u8>, DownloadError> {
+        match self {
+            PhotoSize::Empty(_) => Ok(Vec::new()),
+            PhotoSize::Cached(size) => Ok(size.bytes.clone()),
+            PhotoSize::Stripped(size) => Ok(expand_stripped_thumbnail(&size.bytes)),
+            PhotoSize::Path(size) => Ok(size.to_svg().into_bytes()),
+            PhotoSize::Size(_) | PhotoSize::Progressive(_) => {
+                let mut buffer = Vec::with_capacity(self.size());
+                self.download_into(&mut buffer).await?;
+                Ok(buffer)
             }
         }
     }
 
+    /// Position of the variant, used to order thumbs that are otherwise the same.
+    fn variant_index(&self) -> u8 {
+        match self {
+            PhotoSize::Empty(_) => 0,
+            PhotoSize::Size(_) => 1,
+            PhotoSize::Cached(_) => 2,
+            PhotoSize::Stripped(_) => 3,
+            PhotoSize::Progressive(_) => 4,
+            PhotoSize::Path(_) => 5,
+        }
+    }
+
     pub fn photo_type(&self) -> String {
         match self {
             PhotoSize::Empty(size) => size.photo_type.clone(),
@@ -231,26 +424,126 @@ impl PhotoSize {
     }
 }
 
+/// Thumbs are ordered by resolution, so sorting them goes from the smallest to the largest.
+///
+/// Empty thumbs always sort lowest. The rest are ordered by their [`PhotoSize::size`], then by
+/// their [`PhotoSize::dimensions`] (width first, and thumbs without dimensions before those
+/// with them). Remaining ties are broken by the thumb type and content, so that only equal
+/// thumbs compare as equal.
+impl Ord for PhotoSize {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let is_empty = |x: &PhotoSize| matches!(x, PhotoSize::Empty(_));
+        (!is_empty(self))
+            .cmp(&!is_empty(other))
+            .then_with(|| self.size().cmp(&other.size()))
+            .then_with(|| self.dimensions().cmp(&other.dimensions()))
+            .then_with(|| self.photo_type().cmp(&other.photo_type()))
+            .then_with(|| self.variant_index().cmp(&other.variant_index()))
+            .then_with(|| match (self, other) {
+                (PhotoSize::Size(a), PhotoSize::Size(b)) => a.owner.cmp(&b.owner),
+                (PhotoSize::Cached(a), PhotoSize::Cached(b)) => a.bytes.cmp(&b.bytes),
+                (PhotoSize::Stripped(a), PhotoSize::Stripped(b)) => a.bytes.cmp(&b.bytes),
+                (PhotoSize::Progressive(a), PhotoSize::Progressive(b)) => {
+                    a.sizes.cmp(&b.sizes).then_with(|| a.owner.cmp(&b.owner))
+                }
+                (PhotoSize::Path(a), PhotoSize::Path(b)) => a.bytes.cmp(&b.bytes),
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+impl PartialOrd for PhotoSize {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A concise, single-line description of the thumb, such as
+/// `Size(type=y, 1280x960, 84213 bytes)`, meant for logs and messages.
+impl fmt::Display for PhotoSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            PhotoSize::Empty(_) => return write!(f, "Empty(type={})", self.photo_type()),
+            PhotoSize::Size(_) => "Size",
+            PhotoSize::Cached(_) => "Cached",
+            PhotoSize::Stripped(_) => "Stripped",
+            PhotoSize::Progressive(_) => "Progressive",
+            PhotoSize::Path(_) => "Path",
+        };
+        write!(f, "{}(type={}, ", kind, self.photo_type())?;
+        if let Some((width, height)) = self.dimensions() {
+            write!(f, "{}x{}, ", width, height)?;
+        }
+        write!(f, "{} bytes)", self.size())
+    }
+}
+
 /// Empty thumbnail. Image with this thumbnail is unavailable.
+///
+/// This is only a placeholder with no content, so downloading it fails with
+/// [`DownloadError::NoSize`], without creating any file.
+#[derive(Clone, PartialEq, Eq)]
 pub struct SizeEmpty {
     photo_type: String,
 }
 
 /// Image description. An additional request to Telegram should be perfomed to download the image
+#[derive(Clone)]
 pub struct Size {
     photo_type: String,
     pub width: i32,
     pub height: i32,
     pub size: i32,
 
-    id: i64,
-    access_hash: i64,
-    file_reference: Vec<u8>,
+    owner: ThumbOwner,
+    client: Option<Client>,
+}
 
-    client: Client,
+// The client is left out, as it doesn't change which thumb this is.
+impl PartialEq for Size {
+    fn eq(&self, other: &Self) -> bool {
+        self.photo_type == other.photo_type
+            && self.width == other.width
+            && self.height == other.height
+            && self.size == other.size
+            && self.owner == other.owner
+    }
+}
+
+impl Eq for Size {}
+
+impl Size {
+    /// Attach the client used to download the thumb, replacing the previous one (if any).
+    ///
+    /// Thumbs are only detached when the photo or document they belong to is, so attaching the
+    /// client to that instead means any of its thumbs can be downloaded.
+    pub fn set_client(&mut self, client: Client) {
+        self.client = Some(client);
+    }
+
+    /// Like [`Size::set_client`], but taking and returning the thumb to allow chaining.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.set_client(client);
+        self
+    }
+
+    fn to_input_location(&self) -> tl::enums::InputFileLocation {
+        self.owner.to_input_location(&self.photo_type)
+    }
+
+    /// Stream the contents of the thumb, chunk by chunk, as they are downloaded.
+    pub fn download_stream(&self) -> impl Stream<Item = Result<Bytes, DownloadError>> {
+        match attached(&self.client) {
+            Ok(client) => {
+                Either::Left(client.download_location_stream(Some(self.to_input_location())))
+            }
+            Err(e) => Either::Right(stream::once(async { Err(e) })),
+        }
+    }
 }
 
 /// Description of an image and its content.
+#[derive(Clone, PartialEq, Eq)]
 pub struct CachedSize {
     photo_type: String,
 
@@ -260,36 +553,679 @@ pub struct CachedSize {
 }
 
 /// A low-resolution compressed JPG payload
+#[derive(Clone, PartialEq, Eq)]
 pub struct StrippedSize {
     photo_type: String,
 
     pub bytes: Vec<u8>,
 }
 
+impl StrippedSize {
+    fn dimensions(&self) -> Option<(i32, i32)> {
+        let bytes = &self.bytes;
+        if bytes.len() < 3 || bytes[0] != 0x01 {
+            return None;
+        }
+        // The payload stores the height before the width, as the JPEG header does.
+        Some((bytes[2] as i32, bytes[1] as i32))
+    }
+
+    /// The width of the thumbnail, in pixels, as stored in its payload, or 0 if the payload is
+    /// not a valid stripped thumbnail.
+    pub fn width(&self) -> i32 {
+        self.dimensions().map(|(width, _)| width).unwrap_or(0)
+    }
+
+    /// The height of the thumbnail, in pixels, as stored in its payload, or 0 if the payload is
+    /// not a valid stripped thumbnail.
+    pub fn height(&self) -> i32 {
+        self.dimensions().map(|(_, height)| height).unwrap_or(0)
+    }
+}
+
 /// Progressively encoded photosize
+#[derive(Clone)]
 pub struct ProgressiveSize {
     photo_type: String,
 
     pub width: i32,
     pub height: i32,
+    /// Sizes of the file prefixes which can be used to preliminarily show the image, in
+    /// increasing order of quality. The last one is the size of the entire file.
     pub sizes: Vec<i32>,
+
+    owner: ThumbOwner,
+    client: Option<Client>,
+}
+
+// The client is left out, as it doesn't change which thumb this is.
+impl PartialEq for ProgressiveSize {
+    fn eq(&self, other: &Self) -> bool {
+        self.photo_type == other.photo_type
+            && self.width == other.width
+            && self.height == other.height
+            && self.sizes == other.sizes
+            && self.owner == other.owner
+    }
+}
+
+impl Eq for ProgressiveSize {}
+
+impl ProgressiveSize {
+    fn to_input_location(&self) -> tl::enums::InputFileLocation {
+        self.owner.to_input_location(&self.photo_type)
+    }
+
+    /// Download only the first `sizes[level]` bytes of the image into the defined location.
+    ///
+    /// Because the image is progressively encoded, the prefix is a valid (although lower
+    /// quality) image, which can be fetched quicker than the complete file.
+    ///
+    /// Returns an I/O error of kind `InvalidInput` if `level` is not a valid index into `sizes`.
+    pub async fn download_prefix<P: AsRef<Path>>(
+        &self,
+        path: P,
+        level: usize,
+    ) -> Result<(), DownloadError> {
+        let len = match self.sizes.get(level) {
+            Some(len) => *len as usize,
+            None => {
+                return Err(DownloadError::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "progressive level {} out of range for {} sizes",
+                        level,
+                        self.sizes.len()
+                    ),
+                )))
+            }
+        };
+
+        attached(&self.client)?
+            .download_media_prefix_at_location(self.to_input_location(), path, len)
+            .await
+    }
 }
 
 /// Messages with animated stickers can have a compressed svg (< 300 bytes) to show the outline
 /// of the sticker before fetching the actual lottie animation.
+#[derive(Clone, PartialEq, Eq)]
 pub struct PathSize {
     photo_type: String,
 
     pub bytes: Vec<u8>,
 }
 
+impl PathSize {
+    /// Decode the outline into the commands of a SVG path, suitable for its `d` attribute.
+    ///
+    /// Based on https://core.telegram.org/api/files#vector-thumbnails
+    fn decode_path(&self) -> String {
+        const LOOKUP: &[u8] = b"AACAAAAHAAALMAAAQASTAVAAAZaacaaaahaaalmaaaqastava.az0123456789-,";
+
+        let mut path = String::from("M");
+        for &num in &self.bytes {
+            if num >= 128 + 64 {
+                path.push(LOOKUP[(num - 128 - 64) as usize] as char);
+            } else {
+                if num >= 128 {
+                    path.push(',');
+                } else if num >= 64 {
+                    path.push('-');
+                }
+                path.push_str(&(num & 63).to_string());
+            }
+        }
+        path.push('z');
+        path
+    }
+
+    /// Render the outline as a SVG image of 512x512 units.
+    pub fn to_svg(&self) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<svg version="1.1" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 512 512" xml:space="preserve">
+<path d="{}"/>
+</svg>"#,
+            self.decode_path()
+        )
+    }
+}
+
+/// A video version of a photo, such as the animated loop of a video profile photo.
+///
+/// Like [`Size`], the video has to be fetched from Telegram with an additional request.
+#[derive(Clone)]
+pub struct VideoSize {
+    video_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub size: i32,
+    /// The timestamp, in seconds, of the frame that should be used as the static preview.
+    pub video_start: Option<f64>,
+
+    owner: ThumbOwner,
+    client: Option<Client>,
+}
+
+// The client is left out, as it doesn't change which video this is.
+impl PartialEq for VideoSize {
+    fn eq(&self, other: &Self) -> bool {
+        self.video_type == other.video_type
+            && self.width == other.width
+            && self.height == other.height
+            && self.size == other.size
+            && self.video_start == other.video_start
+            && self.owner == other.owner
+    }
+}
+
+impl VideoSize {
+    pub(crate) fn make_from(
+        size: &tl::enums::VideoSize,
+        owner: &ThumbOwner,
+        client: Option<Client>,
+    ) -> Self {
+        let tl::enums::VideoSize::Size(size) = size;
+        Self {
+            video_type: size.r#type.clone(),
+            width: size.w,
+            height: size.h,
+            size: size.size,
+            video_start: size.video_start_ts,
+            owner: owner.clone(),
+            client,
+        }
+    }
+
+    /// The type of the video size, which identifies it among the other sizes of the photo.
+    pub fn video_type(&self) -> &str {
+        self.video_type.as_str()
+    }
+
+    fn to_input_location(&self) -> tl::enums::InputFileLocation {
+        self.owner.to_input_location(&self.video_type)
+    }
+
+    /// Download the video, normally in MP4 format, into the file at the given path.
+    pub async fn download<P: AsRef<Path>>(&self, path: P) -> Result<(), DownloadError> {
+        let client = attached(&self.client)?;
+        let mut file = fs::File::create(path).await?;
+        client
+            .download_location_into(
+                self.to_input_location(),
+                &mut file,
+                &DownloadOptions::default(),
+                Some(self.size as u64),
+                |_, _| {},
+            )
+            .await?;
+        Ok(())
+    }
+}
+
 pub trait VecExt {
     /// Helper method to get the largest photo thumb
     fn largest(&self) -> Option<&PhotoSize>;
+
+    /// Helper method to get the smallest photo thumb
+    ///
+    /// Empty thumbs are never returned, because they can't be downloaded.
+    fn smallest(&self) -> Option<&PhotoSize>;
+
+    /// Helper method to get the photo thumb with the given type, such as `"m"` or `"x"`.
+    ///
+    /// https://core.telegram.org/api/files#image-thumbnail-types
+    fn thumb_of_type(&self, ty: &str) -> Option<&PhotoSize>;
+
+    /// Helper method to get the largest photo thumb that is at most `max_bytes` big.
+    ///
+    /// Empty thumbs are never returned.
+    fn largest_under(&self, max_bytes: usize) -> Option<&PhotoSize>;
+
+    /// Helper method to get the photo thumb with the width closest to `target_width`, preferring
+    /// the larger thumb if two are just as close.
+    ///
+    /// Only thumbs with known dimensions are considered, so empty, stripped and path thumbs are
+    /// never returned.
+    fn closest_to(&self, target_width: i32) -> Option<&PhotoSize>;
 }
 
 impl VecExt for Vec<PhotoSize> {
     fn largest(&self) -> Option<&PhotoSize> {
         self.iter().max_by_key(|x| x.size())
     }
+
+    fn smallest(&self) -> Option<&PhotoSize> {
+        self.iter()
+            .filter(|x| !matches!(x, PhotoSize::Empty(_)))
+            .min_by_key(|x| x.size())
+    }
+
+    fn thumb_of_type(&self, ty: &str) -> Option<&PhotoSize> {
+        self.iter().find(|x| x.photo_type() == ty)
+    }
+
+    fn largest_under(&self, max_bytes: usize) -> Option<&PhotoSize> {
+        self.iter()
+            .filter(|x| !matches!(x, PhotoSize::Empty(_)) && x.size() <= max_bytes)
+            .max_by_key(|x| x.size())
+    }
+
+    fn closest_to(&self, target_width: i32) -> Option<&PhotoSize> {
+        self.iter()
+            .filter(|x| !matches!(x, PhotoSize::Stripped(_)))
+            .filter_map(|x| x.dimensions().map(|(width, _)| (x, width)))
+            // Widened so that the distance to extreme targets can't overflow.
+            .min_by_key(|&(_, width)| {
+                (
+                    (i64::from(width) - i64::from(target_width)).abs(),
+                    Reverse(width),
+                )
+            })
+            .map(|(x, _)| x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_sizes() {
+        let size = PhotoSize::make_from(
+            &tl::types::PhotoSize {
+                r#type: "y".to_string(),
+                w: 1280,
+                h: 960,
+                size: 84213,
+            }
+            .into(),
+            &ThumbOwner::Photo {
+                id: 1,
+                access_hash: 2,
+                file_reference: Bytes::from_static(&[3]),
+            },
+            None,
+        );
+        let empty = PhotoSize::Empty(SizeEmpty {
+            photo_type: "s".to_string(),
+        });
+        let path = PhotoSize::Path(PathSize {
+            photo_type: "j".to_string(),
+            bytes: vec![0; 60],
+        });
+
+        assert_eq!(size.to_string(), "Size(type=y, 1280x960, 84213 bytes)");
+        assert_eq!(empty.to_string(), "Empty(type=s)");
+        assert_eq!(path.to_string(), "Path(type=j, 60 bytes)");
+    }
+
+    #[test]
+    fn compare_sizes_ignoring_client() {
+        let raw = tl::types::PhotoSize {
+            r#type: "y".to_string(),
+            w: 1280,
+            h: 960,
+            size: 84213,
+        }
+        .into();
+        let owner = ThumbOwner::Photo {
+            id: 1,
+            access_hash: 2,
+            file_reference: Bytes::from_static(&[3]),
+        };
+        let size = PhotoSize::make_from(&raw, &owner, None);
+
+        assert!(size.clone() == size);
+        let thumbs = [size.clone()];
+        assert!(thumbs.contains(&size));
+
+        let other = PhotoSize::make_from(
+            &raw,
+            &ThumbOwner::Photo {
+                id: 4,
+                access_hash: 2,
+                file_reference: Bytes::from_static(&[3]),
+            },
+            None,
+        );
+        assert!(size != other);
+    }
+
+    #[test]
+    fn share_file_reference_between_sizes() {
+        let photo = tl::types::Photo {
+            has_stickers: false,
+            id: 1,
+            access_hash: 2,
+            file_reference: vec![3; 64],
+            date: 0,
+            sizes: vec![],
+            video_sizes: None,
+            dc_id: 2,
+        };
+        let owner = ThumbOwner::from_photo(&photo);
+        let file_reference = |size: &PhotoSize| match size {
+            PhotoSize::Size(Size {
+                owner: ThumbOwner::Photo { file_reference, .. },
+                ..
+            }) => file_reference.as_ptr(),
+            _ => panic!("expected a size"),
+        };
+        let raw = |ty: &str| {
+            tl::types::PhotoSize {
+                r#type: ty.to_string(),
+                w: 90,
+                h: 90,
+                size: 100,
+            }
+            .into()
+        };
+
+        let small = PhotoSize::make_from(&raw("s"), &owner, None);
+        let medium = PhotoSize::make_from(&raw("m"), &owner, None);
+        assert_eq!(file_reference(&small), file_reference(&medium));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn detached_video_size() {
+        let size = VideoSize::make_from(
+            &tl::types::VideoSize {
+                r#type: "u".to_string(),
+                w: 640,
+                h: 640,
+                size: 102400,
+                video_start_ts: Some(1.5),
+            }
+            .into(),
+            &ThumbOwner::Photo {
+                id: 1,
+                access_hash: 2,
+                file_reference: Bytes::from_static(&[3]),
+            },
+            None,
+        );
+        assert_eq!(size.video_type(), "u");
+        assert_eq!((size.width, size.height, size.size), (640, 640, 102400));
+        assert_eq!(size.video_start, Some(1.5));
+
+        let path = std::env::temp_dir().join("grammers-detached-video-size.mp4");
+        assert!(matches!(
+            size.download(&path).await,
+            Err(DownloadError::Detached)
+        ));
+        assert!(!path.exists());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn download_stripped_size() {
+        let size = PhotoSize::Stripped(StrippedSize {
+            photo_type: "i".to_string(),
+            bytes: vec![0x01, 0x28, 0x1e, 0xaa, 0xbb, 0xcc],
+        });
+        let path = std::env::temp_dir().join("grammers-download-stripped-size.jpg");
+
+        size.download(&path).await.unwrap();
+        let jpeg = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(jpeg.len(), size.size());
+        assert_eq!(&jpeg[..2], &[0xff, 0xd8]);
+        assert_eq!(&jpeg[jpeg.len() - 2..], &[0xff, 0xd9]);
+        assert_eq!((jpeg[164], jpeg[166]), (0x28, 0x1e));
+        assert_eq!(&jpeg[623..jpeg.len() - 2], &[0xaa, 0xbb, 0xcc]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn local_size_bytes() {
+        let empty = PhotoSize::Empty(SizeEmpty {
+            photo_type: "s".to_string(),
+        });
+        let cached = PhotoSize::Cached(CachedSize {
+            photo_type: "s".to_string(),
+            width: 2,
+            height: 2,
+            bytes: vec![1, 2, 3],
+        });
+        let stripped = PhotoSize::Stripped(StrippedSize {
+            photo_type: "i".to_string(),
+            bytes: vec![0x01, 0x28, 0x1e, 0xaa],
+        });
+
+        assert!(empty.bytes().await.unwrap().is_empty());
+        assert_eq!(cached.bytes().await.unwrap(), vec![1, 2, 3]);
+        assert_eq!(
+            stripped.bytes().await.unwrap(),
+            expand_stripped_thumbnail(&[0x01, 0x28, 0x1e, 0xaa])
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn download_empty_size() {
+        let empty = PhotoSize::Empty(SizeEmpty {
+            photo_type: "s".to_string(),
+        });
+        let path = std::env::temp_dir().join("grammers-download-empty-size.jpg");
+
+        assert!(matches!(
+            empty.download(&path).await,
+            Err(DownloadError::NoSize)
+        ));
+        assert!(!path.exists());
+        assert!(matches!(
+            empty.download_into(&mut Vec::new()).await,
+            Err(DownloadError::NoSize)
+        ));
+    }
+
+    #[test]
+    fn local_size_dimensions() {
+        let empty = PhotoSize::Empty(SizeEmpty {
+            photo_type: "s".to_string(),
+        });
+        let cached = PhotoSize::Cached(CachedSize {
+            photo_type: "s".to_string(),
+            width: 90,
+            height: 60,
+            bytes: vec![],
+        });
+        let stripped = PhotoSize::Stripped(StrippedSize {
+            photo_type: "i".to_string(),
+            bytes: vec![0x01, 0x1b, 0x28],
+        });
+        let malformed = PhotoSize::Stripped(StrippedSize {
+            photo_type: "i".to_string(),
+            bytes: vec![0x01],
+        });
+        let path = PhotoSize::Path(PathSize {
+            photo_type: "j".to_string(),
+            bytes: vec![],
+        });
+
+        assert_eq!(empty.dimensions(), None);
+        assert_eq!(cached.dimensions(), Some((90, 60)));
+        assert_eq!(stripped.dimensions(), Some((40, 27)));
+        assert_eq!(malformed.dimensions(), None);
+        assert_eq!(path.dimensions(), None);
+    }
+
+    fn cached(photo_type: &str, len: usize) -> PhotoSize {
+        PhotoSize::Cached(CachedSize {
+            photo_type: photo_type.to_string(),
+            width: 0,
+            height: 0,
+            bytes: vec![0; len],
+        })
+    }
+
+    #[test]
+    fn select_thumb_from_empty_vec() {
+        let thumbs: Vec<PhotoSize> = Vec::new();
+        assert!(thumbs.largest().is_none());
+        assert!(thumbs.smallest().is_none());
+        assert!(thumbs.thumb_of_type("m").is_none());
+    }
+
+    #[test]
+    fn select_thumb_by_size_and_type() {
+        let thumbs = vec![cached("m", 20), cached("s", 10), cached("x", 30)];
+        assert_eq!(thumbs.largest().unwrap().photo_type(), "x");
+        assert_eq!(thumbs.smallest().unwrap().photo_type(), "s");
+        assert_eq!(thumbs.thumb_of_type("m").unwrap().size(), 20);
+        assert!(thumbs.thumb_of_type("y").is_none());
+    }
+
+    #[test]
+    fn select_thumb_closest_to_width() {
+        let sized = |photo_type: &str, width| {
+            PhotoSize::Cached(CachedSize {
+                photo_type: photo_type.to_string(),
+                width,
+                height: width,
+                bytes: vec![],
+            })
+        };
+        let thumbs = vec![
+            PhotoSize::Stripped(StrippedSize {
+                photo_type: "i".to_string(),
+                bytes: vec![0x01, 0x28, 0x28],
+            }),
+            sized("s", 100),
+            sized("m", 320),
+            sized("x", 800),
+        ];
+        assert_eq!(thumbs.closest_to(300).unwrap().photo_type(), "m");
+        assert_eq!(thumbs.closest_to(560).unwrap().photo_type(), "x");
+        assert_eq!(thumbs.closest_to(40).unwrap().photo_type(), "s");
+        assert!(vec![thumbs[0].clone()].closest_to(40).is_none());
+        assert_eq!(thumbs.closest_to(i32::MIN).unwrap().photo_type(), "s");
+        assert_eq!(thumbs.closest_to(i32::MAX).unwrap().photo_type(), "x");
+    }
+
+    #[test]
+    fn sort_thumbs_by_resolution() {
+        let sized = |photo_type: &str, width| {
+            PhotoSize::Cached(CachedSize {
+                photo_type: photo_type.to_string(),
+                width,
+                height: width,
+                bytes: vec![0; 10],
+            })
+        };
+        let mut thumbs = [
+            cached("x", 30),
+            sized("m", 320),
+            PhotoSize::Empty(SizeEmpty {
+                photo_type: "a".to_string(),
+            }),
+            sized("s", 100),
+            cached("y", 20),
+        ];
+        thumbs.sort();
+
+        let types = thumbs.iter().map(PhotoSize::photo_type).collect::<Vec<_>>();
+        assert_eq!(types, ["a", "s", "m", "y", "x"]);
+        assert_eq!(thumbs[1].cmp(&sized("s", 100)), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn select_largest_thumb_under_limit() {
+        let thumbs = vec![
+            PhotoSize::Empty(SizeEmpty {
+                photo_type: "w".to_string(),
+            }),
+            cached("m", 20),
+            cached("s", 10),
+            cached("x", 30),
+        ];
+        assert_eq!(thumbs.largest_under(25).unwrap().photo_type(), "m");
+        assert_eq!(thumbs.largest_under(30).unwrap().photo_type(), "x");
+        assert!(thumbs.largest_under(5).is_none());
+    }
+
+    #[test]
+    fn select_smallest_thumb_skipping_empty() {
+        let empty = PhotoSize::Empty(SizeEmpty {
+            photo_type: "w".to_string(),
+        });
+        let thumbs = vec![empty.clone(), cached("m", 20), cached("s", 10)];
+        assert_eq!(thumbs.smallest().unwrap().photo_type(), "s");
+        assert!(vec![empty].smallest().is_none());
+    }
+
+    #[test]
+    fn stripped_size_dimensions() {
+        let stripped = |bytes| StrippedSize {
+            photo_type: "i".to_string(),
+            bytes,
+        };
+        let size = stripped(vec![0x01, 0x1b, 0x28, 0xaa]);
+        assert_eq!((size.width(), size.height()), (40, 27));
+        let malformed = stripped(vec![0x01, 0x1b]);
+        assert_eq!((malformed.width(), malformed.height()), (0, 0));
+    }
+
+    #[test]
+    fn expand_malformed_stripped_thumbnail() {
+        assert!(expand_stripped_thumbnail(&[]).is_empty());
+        assert!(expand_stripped_thumbnail(&[0x01, 0x28]).is_empty());
+        assert!(expand_stripped_thumbnail(&[0x02, 0x28, 0x1e, 0xaa]).is_empty());
+    }
+
+    #[test]
+    fn expand_header_only_stripped_thumbnail() {
+        let jpeg = expand_stripped_thumbnail(&[0x01, 0x28, 0x1e]);
+        assert_eq!(jpeg.len(), STRIPPED_HEADER.len() + STRIPPED_FOOTER.len());
+        assert_eq!(&jpeg[jpeg.len() - 2..], &STRIPPED_FOOTER);
+    }
+
+    #[test]
+    fn path_size_to_svg() {
+        let size = PathSize {
+            photo_type: "j".to_string(),
+            bytes: vec![10, 128 + 20, 128 + 64 + 2, 5, 128 + 6, 64 + 1],
+        };
+
+        assert_eq!(size.decode_path(), "M10,20C5,6-1z");
+        assert!(size.to_svg().contains(r#"viewBox="0 0 512 512""#));
+        assert!(size.to_svg().contains(r#"<path d="M10,20C5,6-1z"/>"#));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn download_as_dyn_downloadable() {
+        use crate::types::Downloadable;
+
+        let size = cached("s", 3);
+        let downloadable: &dyn Downloadable = &size;
+        let path = std::env::temp_dir().join("grammers-download-dyn-downloadable.jpg");
+
+        downloadable.download(&path).await.unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes, vec![0; 3]);
+        assert_eq!(downloadable.size_hint(), Some(3));
+        assert_eq!(
+            PhotoSize::Empty(SizeEmpty {
+                photo_type: "s".to_string()
+            })
+            .size_hint(),
+            None
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn report_local_size_progress() {
+        let size = cached("s", 5);
+        let path = std::env::temp_dir().join("grammers-download-local-progress.jpg");
+
+        let mut calls = Vec::new();
+        size.download_with_progress(&path, |done, total| calls.push((done, total)))
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(calls, vec![(5, Some(5))]);
+    }
 }